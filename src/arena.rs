@@ -0,0 +1,152 @@
+// Copyright 2021 Peter Williams <pwil3058@gmail.com> <pwil3058@bigpond.net.au>
+
+//! Storage for the mops of a `RedundantDiscriminationTree`.
+//!
+//! Mops live in an arena and refer to each other by compact `MopId`s so
+//! real and virtual child links are plain id maps, node identity is an
+//! integer comparison and no `Rc`s or interior mutability are needed.
+//!
+//! The arena only ever holds mops that can be reached from the root (which
+//! is always first).  Inclusion only adds mops and the mops that pruning,
+//! exclusion or merging make unnecessary are discarded by compacting the
+//! arena, so a `MopId` is only meaningful until the next such change.
+//!
+//! The arena is copy on write so cloning it (e.g. for a snapshot) is O(1)
+//! and the clone shares every mop with the original until one of them
+//! changes that mop, at which point the one changing it gets its own copy.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use ordered_collections::OrderedMap;
+
+use crate::strength::Strength;
+use crate::Mop;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MopId(u32);
+
+impl MopId {
+    pub const ROOT: Self = Self(0);
+
+    pub(crate) fn from_index(index: usize) -> Self {
+        Self(u32::try_from(index).expect("mop arena is full"))
+    }
+
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Arena<T: Ord + Debug + Clone, S: Strength> {
+    mops: Arc<Vec<Arc<Mop<T, S>>>>,
+}
+
+impl<T: Ord + Debug + Clone, S: Strength> Arena<T, S> {
+    /// An arena holding only `root`.
+    pub(crate) fn new(root: Mop<T, S>) -> Self {
+        Self {
+            mops: Arc::new(vec![Arc::new(root)]),
+        }
+    }
+
+    /// An arena holding `mops` (the first of which is the root) whose links
+    /// already refer to each other by their positions.
+    pub(crate) fn from_mops(mops: Vec<Mop<T, S>>) -> Self {
+        Self {
+            mops: Arc::new(mops.into_iter().map(Arc::new).collect()),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.mops.len()
+    }
+
+    pub(crate) fn ids(&self) -> impl Iterator<Item = MopId> {
+        (0..self.mops.len()).map(MopId::from_index)
+    }
+
+    pub(crate) fn get(&self, id: MopId) -> &Mop<T, S> {
+        &self.mops[id.index()]
+    }
+
+    pub(crate) fn get_mut(&mut self, id: MopId) -> &mut Mop<T, S> {
+        Arc::make_mut(&mut Arc::make_mut(&mut self.mops)[id.index()])
+    }
+
+    pub(crate) fn add(&mut self, mop: Mop<T, S>) -> MopId {
+        let id = MopId::from_index(self.mops.len());
+        Arc::make_mut(&mut self.mops).push(Arc::new(mop));
+        id
+    }
+
+    /// Whether the mop `id` is shared with the same mop of `other`.
+    #[cfg(test)]
+    pub(crate) fn shares(&self, other: &Self, id: MopId) -> bool {
+        Arc::ptr_eq(&self.mops[id.index()], &other.mops[id.index()])
+    }
+
+    /// Discard the mops for which `keep` is `false` (which must include any
+    /// mop that a kept mop links to) and renumber the rest, in the same
+    /// order, updating their links.  Mops whose links are unchanged remain
+    /// shared.
+    pub(crate) fn retain<F: Fn(MopId) -> bool>(&mut self, keep: F) {
+        let mut new_ids = vec![None; self.mops.len()];
+        let mut count = 0;
+        for id in self.ids() {
+            if keep(id) {
+                new_ids[id.index()] = Some(MopId::from_index(count));
+                count += 1;
+            }
+        }
+        if count == self.mops.len() {
+            return;
+        }
+        let new_id = |id: &MopId| new_ids[id.index()].expect("link to discarded mop");
+        let renumber = |id: &mut MopId| *id = new_id(id);
+        let mops = std::mem::take(Arc::make_mut(&mut self.mops));
+        let kept = Arc::make_mut(&mut self.mops);
+        for (index, mut mop) in mops.into_iter().enumerate() {
+            if new_ids[index].is_some() {
+                let moved = |links: &OrderedMap<T, MopId>| {
+                    links.iter().any(|(_, child)| new_id(child) != *child)
+                };
+                if moved(&mop.children_r) || moved(&mop.children_v) {
+                    let mop = Arc::make_mut(&mut mop);
+                    mop.children_r.values_mut().for_each(renumber);
+                    mop.children_v.values_mut().for_each(renumber);
+                }
+                kept.push(mop);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::strength::SimpleStrength;
+    use crate::tests::pseudo_random_excerpts;
+    use crate::RedundantDiscriminationTree;
+
+    #[test]
+    fn only_reachable_mops_are_kept() {
+        let excerpts = pseudo_random_excerpts(40, 5);
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for excerpt in excerpts.iter() {
+            rdt.include_excerpt(excerpt.clone());
+        }
+        assert!(rdt.reachable().iter().all(|reachable| *reachable));
+        let before = rdt.mops.len();
+        for excerpt in excerpts.iter().step_by(2) {
+            rdt.exclude_excerpt(excerpt);
+        }
+        assert!(rdt.mops.len() < before);
+        assert!(rdt.reachable().iter().all(|reachable| *reachable));
+        assert_eq!(rdt.all_mops().len(), rdt.mops.len());
+        for id in rdt.mops.ids() {
+            let mop = rdt.mop(id);
+            assert_eq!(rdt.complete_match(mop.elements()).unwrap().id(), id);
+        }
+    }
+}
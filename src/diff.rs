@@ -0,0 +1,219 @@
+// Copyright 2021 Peter Williams <pwil3058@gmail.com> <pwil3058@bigpond.net.au>
+
+//! Structural comparison of two trees (e.g. two checkpoints of the same
+//! memory).  Mops are identified by their elements so the trees need not
+//! share any structure.
+
+use std::fmt::Debug;
+
+use ordered_collections::OrderedSet;
+
+use crate::strength::Strength;
+use crate::{MopRef, RedundantDiscriminationTree};
+
+/// The strengths of a mop that can change between trees.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StrengthKind {
+    Trace,
+    Epitome,
+    Undif,
+    Label(String),
+}
+
+/// A strength of the mop with `elements` that differs between the trees.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrengthChange<T: Ord + Debug + Clone> {
+    pub elements: OrderedSet<T>,
+    pub kind: StrengthKind,
+    pub before: f64,
+    pub after: f64,
+}
+
+/// How a tree differs from an earlier one.  All lists are in element order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeDiff<T: Ord + Debug + Clone> {
+    pub traces_added: Vec<OrderedSet<T>>,
+    pub traces_removed: Vec<OrderedSet<T>>,
+    pub epitomes_added: Vec<OrderedSet<T>>,
+    pub epitomes_removed: Vec<OrderedSet<T>>,
+    pub strength_changes: Vec<StrengthChange<T>>,
+}
+
+impl<T: Ord + Debug + Clone> TreeDiff<T> {
+    pub fn is_empty(&self) -> bool {
+        self.traces_added.is_empty()
+            && self.traces_removed.is_empty()
+            && self.epitomes_added.is_empty()
+            && self.epitomes_removed.is_empty()
+            && self.strength_changes.is_empty()
+    }
+}
+
+fn elements_of<'a, T: Ord + Debug + Clone + 'a, S: Strength + 'a>(
+    mops: impl Iterator<Item = &'a MopRef<'a, T, S>>,
+) -> Vec<OrderedSet<T>> {
+    mops.map(|mop| mop.elements().clone()).collect()
+}
+
+/// The differences between `before` and `after`.  Strength changes are
+/// only reported for mops that are in both trees and only if they are
+/// greater than `tolerance`.  Trace (epitome) strengths are only compared
+/// if the mop is a trace (epitome) in both trees as otherwise the mop will
+/// appear in the traces (epitomes) added or removed.  A label missing from
+/// either tree has zero strength there.
+pub fn diff<T: Ord + Debug + Clone, S: Strength>(
+    before: &RedundantDiscriminationTree<T, S>,
+    after: &RedundantDiscriminationTree<T, S>,
+    tolerance: f64,
+) -> TreeDiff<T> {
+    let before_traces = before.traces();
+    let after_traces = after.traces();
+    let before_epitomes = before.epitomes();
+    let after_epitomes = after.epitomes();
+    let mut strength_changes = vec![];
+    let mut check = |elements: &OrderedSet<T>, kind: StrengthKind, was: f64, is: f64| {
+        if (is - was).abs() > tolerance {
+            strength_changes.push(StrengthChange {
+                elements: elements.clone(),
+                kind,
+                before: was,
+                after: is,
+            });
+        }
+    };
+    for was in before.all_mops().iter() {
+        let elements = was.elements();
+        let is = match after.complete_match(elements) {
+            Some(mop) if mop.elements() == elements => mop,
+            _ => continue,
+        };
+        if was.is_trace() && is.is_trace() {
+            check(
+                elements,
+                StrengthKind::Trace,
+                was.trace_strength(),
+                is.trace_strength(),
+            );
+        }
+        if was.is_epitome() && is.is_epitome() {
+            check(
+                elements,
+                StrengthKind::Epitome,
+                was.epitome_strength(),
+                is.epitome_strength(),
+            );
+        }
+        check(
+            elements,
+            StrengthKind::Undif,
+            was.undif_strength(),
+            is.undif_strength(),
+        );
+        let mut labels = was.labels();
+        labels.extend(is.labels());
+        labels.sort();
+        labels.dedup();
+        for label in labels {
+            let (was_strength, is_strength) =
+                (was.label_strength(&label), is.label_strength(&label));
+            check(
+                elements,
+                StrengthKind::Label(label),
+                was_strength,
+                is_strength,
+            );
+        }
+    }
+    TreeDiff {
+        traces_added: elements_of(after_traces.difference(&before_traces)),
+        traces_removed: elements_of(before_traces.difference(&after_traces)),
+        epitomes_added: elements_of(after_epitomes.difference(&before_epitomes)),
+        epitomes_removed: elements_of(before_epitomes.difference(&after_epitomes)),
+        strength_changes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strength::{CountStrength, SimpleStrength};
+    use crate::tests::pseudo_random_excerpts;
+
+    #[test]
+    fn diff_reports_structural_changes() {
+        let mut before = RedundantDiscriminationTree::<&str, CountStrength>::new();
+        before.include_experience(&["a", "b", "c"]);
+        before.include_experience(&["a", "d"]);
+        assert!(diff(&before, &before, 0.0).is_empty());
+
+        let mut after = RedundantDiscriminationTree::<&str, CountStrength>::new();
+        after.include_experience(&["a", "b", "c"]);
+        after.include_experience(&["a", "b", "c"]);
+        after.include_experience(&["b", "e"]);
+        after.include_labelled_excerpt(vec!["b", "c"].into(), "x");
+        let changes = diff(&before, &after, 0.0);
+        assert_eq!(
+            changes.traces_added,
+            vec![vec!["b", "c"].into(), vec!["b", "e"].into()]
+        );
+        assert_eq!(changes.traces_removed, vec![vec!["a", "d"].into()]);
+        assert_eq!(
+            changes.epitomes_added,
+            vec![vec!["b"].into(), vec!["b", "c"].into()]
+        );
+        assert_eq!(changes.epitomes_removed, vec![vec!["a"].into()]);
+        let change = |elements: Vec<&'static str>, kind, before, after| StrengthChange {
+            elements: elements.into(),
+            kind,
+            before,
+            after,
+        };
+        assert_eq!(
+            changes.strength_changes,
+            vec![
+                change(vec![], StrengthKind::Epitome, 2.0, 4.0),
+                change(vec![], StrengthKind::Undif, 2.0, 4.0),
+                change(vec![], StrengthKind::Label("x".to_string()), 0.0, 1.0),
+                change(vec!["a", "b", "c"], StrengthKind::Trace, 1.0, 2.0),
+                change(vec!["a", "b", "c"], StrengthKind::Undif, 1.0, 2.0),
+            ]
+        );
+        assert_eq!(diff(&before, &after, 1.5).strength_changes.len(), 2);
+
+        let reversed = diff(&after, &before, 0.0);
+        assert_eq!(reversed.traces_added, changes.traces_removed);
+        assert_eq!(reversed.epitomes_removed, changes.epitomes_added);
+    }
+
+    #[test]
+    fn diff_between_checkpoints() {
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for excerpt in pseudo_random_excerpts(40, 137) {
+            rdt.include_excerpt(excerpt);
+        }
+        let mut bytes = vec![];
+        rdt.save(&mut bytes).unwrap();
+        let checkpoint =
+            RedundantDiscriminationTree::<u8, SimpleStrength>::load(&mut &bytes[..]).unwrap();
+        assert!(diff(&checkpoint, &rdt, 0.0).is_empty());
+
+        rdt.decrement_strengths();
+        let decayed = diff(&checkpoint, &rdt, 0.0);
+        assert!(decayed.traces_added.is_empty() && decayed.epitomes_added.is_empty());
+        let undif_changes = decayed
+            .strength_changes
+            .iter()
+            .filter(|change| change.kind == StrengthKind::Undif)
+            .count();
+        assert_eq!(undif_changes, rdt.all_mops().len());
+        assert!(decayed
+            .strength_changes
+            .iter()
+            .all(|change| change.after < change.before));
+        assert!(diff(&checkpoint, &rdt, 0.1).is_empty());
+
+        let trace = rdt.traces().iter().next().unwrap().elements().clone();
+        assert!(rdt.exclude_excerpt(&trace));
+        assert_eq!(diff(&checkpoint, &rdt, 1.0).traces_removed, vec![trace]);
+    }
+}
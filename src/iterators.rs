@@ -0,0 +1,319 @@
+// Copyright 2021 Peter Williams <pwil3058@gmail.com> <pwil3058@bigpond.net.au>
+
+//! Lazy versions of `traces()`, `epitomes()` and `partial_matches()`.
+//!
+//! Rather than merging result sets at each level of recursion these walk
+//! the tree on demand.  A child is only descended into via the first of its
+//! indices (that is after the index that led to the current mop) which is
+//! the rule algorithms B.8, B.10 and 6.15 use to ensure each mop is visited
+//! exactly once.  So no mop is yielded twice and the mops yielded are
+//! exactly those in the corresponding set and, like the sets, in element
+//! order.
+
+use std::cmp::Ordering;
+use std::fmt::Debug;
+
+use ordered_collections::OrderedSet;
+
+use crate::arena::MopId;
+use crate::strength::Strength;
+use crate::{Mop, MopRef, RedundantDiscriminationTree};
+
+// A mop still to be visited and the index (if any) that led to it
+pub(crate) type Pending<T> = (MopId, Option<T>);
+
+// A mop on the current path and its children that are still to be visited.
+// Every mop below the child reached via `j` has `j` as the first element
+// that it has and this mop does not so they all come before this mop in
+// element order if `j` is less than this mop's last element and after it
+// otherwise.
+struct Frame<T> {
+    id: MopId,
+    children: std::vec::IntoIter<(T, MopId)>,
+    next_child: Option<(T, MopId)>,
+    last: Option<T>,
+    visited: bool,
+}
+
+struct Walk<'a, T: Ord + Debug + Clone, S: Strength> {
+    tree: &'a RedundantDiscriminationTree<T, S>,
+    stack: Vec<Frame<T>>,
+}
+
+impl<'a, T: Ord + Debug + Clone, S: Strength> Walk<'a, T, S> {
+    fn new(tree: &'a RedundantDiscriminationTree<T, S>) -> Self {
+        let mut walk = Self {
+            tree,
+            stack: vec![],
+        };
+        walk.push(MopId::ROOT, None);
+        walk
+    }
+
+    fn push(&mut self, id: MopId, k: Option<&T>) {
+        let children: Vec<(T, MopId)> = self
+            .tree
+            .first_index_children(id, k)
+            .map(|(j, j_mop)| (j.clone(), j_mop))
+            .collect();
+        let mut children = children.into_iter();
+        self.stack.push(Frame {
+            id,
+            next_child: children.next(),
+            children,
+            last: self.tree.elements(id).iter().last().cloned(),
+            visited: false,
+        });
+    }
+
+    // Algorithms B.8 and B.10 without the recursion
+    fn next_where<P: Fn(&Mop<T, S>) -> bool>(&mut self, wanted: P) -> Option<MopRef<'a, T, S>> {
+        let tree = self.tree;
+        while let Some(frame) = self.stack.last_mut() {
+            let visit_now = !frame.visited
+                && match (&frame.next_child, &frame.last) {
+                    (Some((j, _)), Some(last)) => j > last,
+                    _ => true,
+                };
+            if visit_now {
+                frame.visited = true;
+                if wanted(tree.mops.get(frame.id)) {
+                    return Some(tree.mop(frame.id));
+                }
+            } else if let Some((j, j_mop)) = frame.next_child.take() {
+                frame.next_child = frame.children.next();
+                self.push(j_mop, Some(&j));
+            } else {
+                self.stack.pop();
+            }
+        }
+        None
+    }
+}
+
+pub struct Traces<'a, T: Ord + Debug + Clone, S: Strength> {
+    walk: Walk<'a, T, S>,
+}
+
+impl<'a, T: Ord + Debug + Clone, S: Strength> Iterator for Traces<'a, T, S> {
+    type Item = MopRef<'a, T, S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.walk.next_where(Mop::is_trace)
+    }
+}
+
+pub struct Epitomes<'a, T: Ord + Debug + Clone, S: Strength> {
+    walk: Walk<'a, T, S>,
+}
+
+impl<'a, T: Ord + Debug + Clone, S: Strength> Iterator for Epitomes<'a, T, S> {
+    type Item = MopRef<'a, T, S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.walk.next_where(Mop::is_epitome)
+    }
+}
+
+// The partial matches below a mop in element order.  The query's indices
+// do not split the matches into runs that are in element order (as the
+// first index of a child need not be the first of its new elements) so
+// the matches below each child are merged, expanding a child only when its
+// first match is needed for the comparison.
+enum Matches<T> {
+    Unexpanded(Pending<T>),
+    Match(MopId),
+    // the streams and (once known) which one has the next match
+    Merge(Vec<Matches<T>>, Option<usize>),
+    Done,
+}
+
+impl<T: Ord + Debug + Clone> Matches<T> {
+    // Algorithm 6.15 for one mop
+    fn expand<S: Strength>(
+        &mut self,
+        tree: &RedundantDiscriminationTree<T, S>,
+        query: &OrderedSet<T>,
+    ) {
+        if let Matches::Unexpanded((id, k)) = self {
+            let mop = tree.mops.get(*id);
+            *self = if mop.is_disjoint_child_indices(query) {
+                if query.is_disjoint(&mop.elements) {
+                    Matches::Done
+                } else {
+                    Matches::Match(*id)
+                }
+            } else {
+                let mut children = vec![];
+                for j in query.difference(&mop.elements) {
+                    if k.as_ref().is_some_and(|k| j <= k) {
+                        continue;
+                    }
+                    if let Some(j_mop) = tree.get_child(*id, j) {
+                        if Some(j) == tree.first_index(*id, j_mop, query) {
+                            children.push(Matches::Unexpanded((j_mop, Some(j.clone()))));
+                        }
+                    }
+                }
+                Matches::Merge(children, None)
+            };
+        }
+    }
+
+    fn peek<S: Strength>(
+        &mut self,
+        tree: &RedundantDiscriminationTree<T, S>,
+        query: &OrderedSet<T>,
+    ) -> Option<MopId> {
+        self.expand(tree, query);
+        match self {
+            Matches::Unexpanded(_) | Matches::Done => None,
+            Matches::Match(id) => Some(*id),
+            Matches::Merge(streams, first) => {
+                if first.is_none() {
+                    let mut best: Option<(usize, MopId)> = None;
+                    for (index, stream) in streams.iter_mut().enumerate() {
+                        if let Some(id) = stream.peek(tree, query) {
+                            let better = best.is_none_or(|(_, best_id)| {
+                                tree.elements(id).cmp(tree.elements(best_id)) == Ordering::Less
+                            });
+                            if better {
+                                best = Some((index, id));
+                            }
+                        }
+                    }
+                    *first = best.map(|(index, _)| index);
+                }
+                first.and_then(|index| streams[index].peek(tree, query))
+            }
+        }
+    }
+
+    fn next<S: Strength>(
+        &mut self,
+        tree: &RedundantDiscriminationTree<T, S>,
+        query: &OrderedSet<T>,
+    ) -> Option<MopId> {
+        let id = self.peek(tree, query)?;
+        match self {
+            Matches::Match(_) => *self = Matches::Done,
+            Matches::Merge(streams, first) => {
+                if let Some(index) = first.take() {
+                    streams[index].next(tree, query);
+                }
+            }
+            Matches::Unexpanded(_) | Matches::Done => (),
+        }
+        Some(id)
+    }
+}
+
+pub struct PartialMatches<'a, 'q, T: Ord + Debug + Clone, S: Strength> {
+    tree: &'a RedundantDiscriminationTree<T, S>,
+    query: &'q OrderedSet<T>,
+    matches: Matches<T>,
+}
+
+impl<'a, 'q, T: Ord + Debug + Clone, S: Strength> Iterator for PartialMatches<'a, 'q, T, S> {
+    type Item = MopRef<'a, T, S>;
+
+    // Algorithm 6.14 one match at a time
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree = self.tree;
+        self.matches.next(tree, self.query).map(|id| tree.mop(id))
+    }
+}
+
+impl<T: Ord + Debug + Clone, S: Strength> RedundantDiscriminationTree<T, S> {
+    /// Iterate over the traces that `traces()` would return without
+    /// building the set.
+    pub fn iter_traces(&self) -> Traces<'_, T, S> {
+        Traces {
+            walk: Walk::new(self),
+        }
+    }
+
+    /// Iterate over the epitomes that `epitomes()` would return without
+    /// building the set.
+    pub fn iter_epitomes(&self) -> Epitomes<'_, T, S> {
+        Epitomes {
+            walk: Walk::new(self),
+        }
+    }
+
+    /// Iterate over the mops that `partial_matches()` would return without
+    /// building the set.
+    pub fn iter_partial_matches<'a, 'q>(
+        &'a self,
+        query: &'q OrderedSet<T>,
+    ) -> PartialMatches<'a, 'q, T, S> {
+        PartialMatches {
+            tree: self,
+            query,
+            matches: Matches::Unexpanded((MopId::ROOT, None)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strength::SimpleStrength;
+    use crate::tests::pseudo_random_excerpts;
+
+    fn elements_of<'a>(
+        mops: impl IntoIterator<Item = MopRef<'a, u8, SimpleStrength>>,
+    ) -> Vec<OrderedSet<u8>> {
+        mops.into_iter().map(|mop| mop.elements().clone()).collect()
+    }
+
+    #[test]
+    fn iterators_agree_with_sets() {
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        assert!(rdt.iter_traces().next().is_none());
+        for excerpt in pseudo_random_excerpts(60, 17) {
+            rdt.include_excerpt(excerpt);
+        }
+        assert_eq!(
+            elements_of(rdt.iter_traces()),
+            elements_of(rdt.traces().iter().cloned())
+        );
+        assert_eq!(
+            elements_of(rdt.iter_epitomes()),
+            elements_of(rdt.epitomes().iter().cloned())
+        );
+        for query in pseudo_random_excerpts(30, 19).iter() {
+            assert_eq!(
+                elements_of(rdt.iter_partial_matches(query)),
+                elements_of(rdt.partial_matches(query).iter().cloned())
+            );
+        }
+        let first = rdt.iter_traces().next().unwrap();
+        assert_eq!(first, rdt.traces().iter().next().unwrap().clone());
+    }
+
+    #[test]
+    fn iterators_yield_element_order() {
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        rdt.include_excerpt(vec![1, 3].into());
+        rdt.include_excerpt(vec![1, 2, 3].into());
+        let expected: Vec<OrderedSet<u8>> = vec![vec![1, 2, 3].into(), vec![1, 3].into()];
+        assert_eq!(elements_of(rdt.iter_traces()), expected);
+        assert_eq!(elements_of(rdt.traces().iter().cloned()), expected);
+        rdt.include_excerpt(vec![2, 4].into());
+        rdt.include_excerpt(vec![3].into());
+        assert_eq!(
+            elements_of(rdt.iter_epitomes()),
+            elements_of(rdt.epitomes().iter().cloned())
+        );
+        let query: OrderedSet<u8> = vec![2, 3, 4].into();
+        let matches = elements_of(rdt.iter_partial_matches(&query));
+        let mut sorted = matches.clone();
+        sorted.sort();
+        assert_eq!(matches, sorted);
+        assert_eq!(
+            matches,
+            elements_of(rdt.partial_matches(&query).iter().cloned())
+        );
+    }
+}
@@ -1,243 +1,397 @@
 extern crate ordered_collections;
 
-use std::cell::{Cell, RefCell};
 use std::cmp::Ordering;
 use std::fmt::Debug;
-use std::rc::Rc;
 
 use ordered_collections::{
-    ordered_map::ord_map_iterators::{SkipAheadMapIterator, ToMap},
-    ordered_set::ord_set_iterators::*,
-    OrderedMap, OrderedSet,
+    ordered_map::ord_map_iterators::ToMap, ordered_set::ord_set_iterators::*, OrderedMap,
+    OrderedSet,
 };
 
+pub mod arena;
+pub mod diff;
+pub mod iterators;
+pub mod merge;
+pub mod mining;
+pub mod persist;
+pub mod queries;
+pub mod snapshot;
 pub mod strength;
+pub mod sync;
 #[cfg(test)]
 mod yardstick;
 
-use crate::strength::Strength;
+use crate::arena::{Arena, MopId};
+use crate::strength::{CountStrength, Strength, StrengthConfig};
 
 #[derive(Clone, Debug)]
-pub struct Mop<T: Ord + Debug + Clone, S: Strength> {
+pub(crate) struct Mop<T: Ord + Debug + Clone, S: Strength> {
     elements: OrderedSet<T>,
-    children_r: RefCell<OrderedMap<T, Rc<Self>>>,
-    children_v: RefCell<OrderedMap<T, Rc<Self>>>,
-    trace_strength: Cell<S>,
-    epitome_strength: Cell<S>,
-    undif_strength: Cell<S>,
+    children_r: OrderedMap<T, MopId>,
+    children_v: OrderedMap<T, MopId>,
+    trace_strength: S,
+    epitome_strength: S,
+    undif_strength: S,
+    label_strengths: OrderedMap<String, S>,
 }
 
 impl<T: Ord + Debug + Clone, S: Strength> Default for Mop<T, S> {
     fn default() -> Self {
         Self {
             elements: OrderedSet::<T>::default(),
-            children_r: RefCell::new(OrderedMap::<T, Rc<Self>>::default()),
-            children_v: RefCell::new(OrderedMap::<T, Rc<Self>>::default()),
-            trace_strength: Cell::new(S::default()),
-            epitome_strength: Cell::new(S::default()),
-            undif_strength: Cell::new(S::default()),
+            children_r: OrderedMap::<T, MopId>::default(),
+            children_v: OrderedMap::<T, MopId>::default(),
+            trace_strength: S::default(),
+            epitome_strength: S::default(),
+            undif_strength: S::default(),
+            label_strengths: OrderedMap::<String, S>::default(),
         }
     }
 }
 
-impl<T: Ord + Debug + Clone, S: Strength> PartialEq for Mop<T, S> {
+impl<T: Ord + Clone + Debug, S: Strength> Mop<T, S> {
+    fn new_trace(
+        elements: OrderedSet<T>,
+        weight: f64,
+        now: u64,
+        config: &StrengthConfig,
+        label: Option<&str>,
+    ) -> Self {
+        let mut trace = Self {
+            elements,
+            children_r: OrderedMap::<T, MopId>::new(),
+            children_v: OrderedMap::<T, MopId>::new(),
+            trace_strength: S::new(false).incremented_at(weight, now, config),
+            epitome_strength: S::new(false),
+            undif_strength: S::new(false).incremented_at(weight, now, config),
+            label_strengths: OrderedMap::<String, S>::new(),
+        };
+        trace.incr_label_strength(label, weight, now, config);
+        trace
+    }
+
+    fn new_epitome(
+        elements: OrderedSet<T>,
+        children_v: OrderedMap<T, MopId>,
+        undif_strength: S,
+        label_strengths: OrderedMap<String, S>,
+    ) -> Self {
+        Self {
+            elements,
+            children_r: OrderedMap::<T, MopId>::new(),
+            children_v,
+            trace_strength: S::new(false),
+            epitome_strength: undif_strength,
+            undif_strength,
+            label_strengths,
+        }
+    }
+
+    fn links(&self, real: bool) -> &OrderedMap<T, MopId> {
+        if real {
+            &self.children_r
+        } else {
+            &self.children_v
+        }
+    }
+
+    fn incr_label_strength(
+        &mut self,
+        label: Option<&str>,
+        weight: f64,
+        now: u64,
+        config: &StrengthConfig,
+    ) {
+        if let Some(label) = label {
+            let label = label.to_string();
+            match self.label_strengths.get_mut(&label) {
+                Some(strength) => strength.increase_at(weight, now, config),
+                None => {
+                    self.label_strengths
+                        .insert(label, S::new(false).incremented_at(weight, now, config));
+                }
+            }
+        }
+    }
+
+    fn decr_strengths(&mut self, config: &StrengthConfig) {
+        self.trace_strength.decrease_with_config(config);
+        self.epitome_strength.decrease_with_config(config);
+        self.undif_strength.decrease_with_config(config);
+        for strength in self.label_strengths.values_mut() {
+            strength.decrease_with_config(config);
+        }
+    }
+
+    fn is_trace(&self) -> bool {
+        self.trace_strength.value() > 0.0
+    }
+
+    fn is_epitome(&self) -> bool {
+        self.children_r.len() > 0 || self.children_v.len() > 0
+    }
+
+    fn is_disjoint_child_indices(&self, set: &OrderedSet<T>) -> bool {
+        self.children_r.keys().is_disjoint(&set.iter())
+            && self.children_v.keys().is_disjoint(&set.iter())
+    }
+
+    fn merged_children(&self) -> OrderedMap<T, MopId> {
+        (self.children_r.iter() | self.children_v.iter()).to_map()
+    }
+}
+
+/// A mop of a tree.  Mops are identified within their tree by their
+/// `MopId` and are compared and ordered by their elements.
+pub struct MopRef<'a, T: Ord + Debug + Clone, S: Strength> {
+    tree: &'a RedundantDiscriminationTree<T, S>,
+    id: MopId,
+}
+
+impl<'a, T: Ord + Debug + Clone, S: Strength> Clone for MopRef<'a, T, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T: Ord + Debug + Clone, S: Strength> Copy for MopRef<'a, T, S> {}
+
+impl<'a, T: Ord + Debug + Clone, S: Strength> Debug for MopRef<'a, T, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MopRef")
+            .field("id", &self.id)
+            .field("elements", self.elements())
+            .finish()
+    }
+}
+
+impl<'a, T: Ord + Debug + Clone, S: Strength> PartialEq for MopRef<'a, T, S> {
     fn eq(&self, other: &Self) -> bool {
-        self.elements == other.elements
+        self.elements() == other.elements()
     }
 }
 
-impl<T: Ord + Debug + Clone, S: Strength> Eq for Mop<T, S> {}
+impl<'a, T: Ord + Debug + Clone, S: Strength> Eq for MopRef<'a, T, S> {}
 
-impl<T: Ord + Debug + Clone, S: Strength> PartialOrd for Mop<T, S> {
+impl<'a, T: Ord + Debug + Clone, S: Strength> PartialOrd for MopRef<'a, T, S> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl<T: Ord + Debug + Clone, S: Strength> Ord for Mop<T, S> {
+impl<'a, T: Ord + Debug + Clone, S: Strength> Ord for MopRef<'a, T, S> {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.elements.cmp(&other.elements)
+        self.elements().cmp(other.elements())
     }
 }
 
-impl<T: Ord + Clone + Debug, S: Strength> Mop<T, S> {
-    pub fn elements(&self) -> &OrderedSet<T> {
-        &self.elements
+impl<'a, T: Ord + Debug + Clone, S: Strength> MopRef<'a, T, S> {
+    fn mop(&self) -> &'a Mop<T, S> {
+        self.tree.mops.get(self.id)
     }
 
-    pub fn trace_strength(&self) -> f64 {
-        self.trace_strength.get().value()
+    // The value of `strength` by the tree's clock
+    fn value_of(&self, strength: &S) -> f64 {
+        strength.value_at(self.tree.clock, &self.tree.config)
     }
 
-    fn incr_trace_strength(&self) {
-        self.trace_strength
-            .set(self.trace_strength.get().incremented());
+    pub fn id(&self) -> MopId {
+        self.id
     }
 
-    fn decr_trace_strength(&self) {
-        self.trace_strength
-            .set(self.trace_strength.get().decremented());
+    pub fn elements(&self) -> &'a OrderedSet<T> {
+        &self.mop().elements
     }
 
-    pub fn epitome_strength(&self) -> f64 {
-        self.epitome_strength.get().value()
+    pub fn trace_strength(&self) -> f64 {
+        self.value_of(&self.mop().trace_strength)
     }
 
-    fn incr_epitome_strength(&self) {
-        self.epitome_strength
-            .set(self.epitome_strength.get().incremented());
+    pub fn epitome_strength(&self) -> f64 {
+        self.value_of(&self.mop().epitome_strength)
     }
 
-    fn decr_epitome_strength(&self) {
-        self.epitome_strength
-            .set(self.epitome_strength.get().decremented());
+    /// The strength of all the excerpts that contain this mop's elements.
+    pub fn undif_strength(&self) -> f64 {
+        self.value_of(&self.mop().undif_strength)
     }
 
-    fn incr_undif_strength(&self) {
-        self.undif_strength
-            .set(self.undif_strength.get().incremented());
+    /// The strength of the excerpts labelled `label` that contain this
+    /// mop's elements.
+    pub fn label_strength(&self, label: &str) -> f64 {
+        self.mop()
+            .label_strengths
+            .get(&label.to_string())
+            .map_or(0.0, |strength| self.value_of(strength))
     }
 
-    fn decr_undif_strength(&self) {
-        self.undif_strength
-            .set(self.undif_strength.get().decremented());
+    /// The labels of the excerpts that contain this mop's elements.
+    pub fn labels(&self) -> Vec<String> {
+        self.mop().label_strengths.keys().cloned().collect()
     }
 
     pub fn is_trace(&self) -> bool {
-        self.trace_strength() > 0.0
+        self.mop().is_trace()
     }
 
     pub fn is_epitome(&self) -> bool {
-        self.children_r.borrow().len() > 0 || self.children_v.borrow().len() > 0
+        self.mop().is_epitome()
+    }
+
+    /// The traces at or below this mop i.e. those that contain its elements.
+    pub fn traces(&self) -> OrderedSet<MopRef<'a, T, S>> {
+        self.tree.algorithm_b8_mod_traces_after(self.id, None)
+    }
+
+    /// The epitomes at or below this mop.
+    pub fn epitomes(&self) -> OrderedSet<MopRef<'a, T, S>> {
+        self.tree.algorithm_b10_mod_epitomes_after(self.id, None)
+    }
+
+    // The mops that this mop links to
+    pub(crate) fn distinct_children(&self) -> OrderedSet<MopRef<'a, T, S>> {
+        let mop = self.mop();
+        let children: Vec<MopRef<'a, T, S>> = mop
+            .children_r
+            .iter()
+            .chain(mop.children_v.iter())
+            .map(|(_, child)| self.tree.mop(*child))
+            .collect();
+        children.into()
     }
 }
 
-// Support Methods
-impl<'a, T: 'a + Ord + Debug + Clone, S: Strength> Mop<T, S> {
-    fn tabula_rasa() -> Rc<Self> {
-        Rc::new(Self::default())
+impl<'a, T: Ord + Clone + Debug> MopRef<'a, T, CountStrength> {
+    /// The number of times this mop's elements have been included as an
+    /// excerpt.
+    pub fn trace_count(&self) -> u64 {
+        self.mop().trace_strength.count()
     }
 
-    fn new_trace(elements: OrderedSet<T>) -> Rc<Self> {
-        Rc::new(Self {
-            elements,
-            children_r: RefCell::new(OrderedMap::<T, Rc<Self>>::new()),
-            children_v: RefCell::new(OrderedMap::<T, Rc<Self>>::new()),
-            trace_strength: Cell::new(S::new(true)),
-            epitome_strength: Cell::new(S::new(false)),
-            undif_strength: Cell::new(S::new(true)),
-        })
+    /// The number of included excerpts that are proper supersets of this
+    /// mop's elements.
+    pub fn epitome_count(&self) -> u64 {
+        self.mop().epitome_strength.count()
     }
 
-    fn new_epitome(
-        elements: OrderedSet<T>,
-        children_v: RefCell<OrderedMap<T, Rc<Self>>>,
-        undif_strength: &S,
-    ) -> Rc<Self> {
-        Rc::new(Self {
-            elements,
-            children_r: RefCell::new(OrderedMap::<T, Rc<Self>>::new()),
-            children_v,
-            trace_strength: Cell::new(S::new(false)),
-            epitome_strength: Cell::new(*undif_strength),
-            undif_strength: Cell::new(*undif_strength),
-        })
+    /// The number of included excerpts that contain this mop's elements.
+    pub fn undif_count(&self) -> u64 {
+        self.mop().undif_strength.count()
     }
+}
+
+#[derive(Debug, Clone)]
+pub struct RedundantDiscriminationTree<T: Ord + Debug + Clone, S: Strength> {
+    mops: Arena<T, S>,
+    config: StrengthConfig,
+    // The number of times that the strengths have been decremented
+    clock: u64,
+}
 
-    fn insert_r_child<I: Iterator<Item = &'a T>>(&self, iter: I, child: &Rc<Self>) {
-        let mut children_r = self.children_r.borrow_mut();
+// Support Methods
+impl<'a, T: 'a + Ord + Debug + Clone, S: Strength> RedundantDiscriminationTree<T, S> {
+    fn elements(&self, id: MopId) -> &OrderedSet<T> {
+        &self.mops.get(id).elements
+    }
+
+    fn insert_r_child<I: Iterator<Item = &'a T>>(&mut self, id: MopId, iter: I, child: MopId) {
+        let children_r = &mut self.mops.get_mut(id).children_r;
         for i in iter {
-            children_r.insert(i.clone(), Rc::clone(child));
+            children_r.insert(i.clone(), child);
         }
     }
 
-    fn insert_v_child<I: Iterator<Item = &'a T>>(&self, iter: I, child: &Rc<Self>) {
-        let mut children_v = self.children_v.borrow_mut();
+    fn insert_v_child<I: Iterator<Item = &'a T>>(&mut self, id: MopId, iter: I, child: MopId) {
+        let children_v = &mut self.mops.get_mut(id).children_v;
         for i in iter {
-            children_v.insert(i.clone(), Rc::clone(child));
+            children_v.insert(i.clone(), child);
         }
     }
 
-    fn delete_v_children<I: Iterator<Item = &'a T>>(&self, iter: I) {
-        let mut children_v = self.children_v.borrow_mut();
+    fn delete_v_children<I: Iterator<Item = &'a T>>(&mut self, id: MopId, iter: I) {
+        let children_v = &mut self.mops.get_mut(id).children_v;
         for i in iter {
             children_v.remove(i);
         }
     }
 
-    fn get_r_child(&self, key: &T) -> Option<Rc<Self>> {
-        let my_children = self.children_r.borrow();
-        my_children.get(key).map(Rc::clone)
+    fn get_r_child(&self, id: MopId, key: &T) -> Option<MopId> {
+        self.mops.get(id).children_r.get(key).copied()
     }
 
-    // See Algorithm 6.5
-    fn get_r_child_and_indices(&self, key: &T) -> Option<(Rc<Self>, OrderedSet<T>)> {
-        let my_children = self.children_r.borrow();
-        if let Some(child) = my_children.get(key) {
-            let mut indices = OrderedSet::<T>::new();
-            for i in child
-                .elements
-                .difference(&self.elements)
-                .difference(self.children_v.borrow().keys())
-            {
-                if let Some(rdt_i) = my_children.get(i) {
-                    if rdt_i == child {
-                        indices.insert(i.clone());
-                    }
-                }
-            }
-            Some((Rc::clone(child), indices))
-        } else {
-            None
-        }
+    fn get_v_child(&self, id: MopId, key: &T) -> Option<MopId> {
+        self.mops.get(id).children_v.get(key).copied()
     }
 
-    fn get_v_child(&self, key: &T) -> Option<Rc<Self>> {
-        let my_children = self.children_v.borrow();
-        my_children.get(key).map(Rc::clone)
+    fn get_child(&self, id: MopId, key: &T) -> Option<MopId> {
+        self.get_r_child(id, key)
+            .or_else(|| self.get_v_child(id, key))
     }
 
-    // See Algorithm 6.8
-    fn get_v_child_and_indices(&self, key: &T) -> Option<(Rc<Self>, OrderedSet<T>)> {
-        let my_children = self.children_v.borrow();
-        if let Some(child) = my_children.get(key) {
-            let mut indices = OrderedSet::<T>::new();
-            for i in child
-                .elements
-                .difference(&self.elements)
-                .difference(self.children_r.borrow().keys())
-            {
-                if let Some(rdt_i) = my_children.get(i) {
-                    if rdt_i == child {
-                        indices.insert(i.clone());
-                    }
-                }
-            }
-            Some((Rc::clone(child), indices))
-        } else {
-            None
-        }
+    // The indices via which `id` has a real (or virtual) link to `child`
+    // i.e. the set that Algorithm 6.5 (or 6.8) builds.
+    fn link_indices<'b>(
+        &'b self,
+        id: MopId,
+        real: bool,
+        child: MopId,
+    ) -> impl Iterator<Item = &'b T> + 'b {
+        let links = self.mops.get(id).links(real);
+        self.elements(child)
+            .difference(self.elements(id))
+            .filter(move |i| links.get(i) == Some(&child))
     }
 
-    fn is_disjoint_child_indices(&self, set: &OrderedSet<T>) -> bool {
-        self.children_r.borrow().keys().is_disjoint(&set.iter())
-            && self.children_v.borrow().keys().is_disjoint(&set.iter())
+    // The first of the indices in `within` (or of all the indices) via
+    // which `id` has a real (or virtual) link to `child`.  Loops over a
+    // mop's children only visit a child via this index rather than building
+    // its index set and removing that from the indices still to be visited.
+    fn first_link_index<'b>(
+        &'b self,
+        id: MopId,
+        real: bool,
+        child: MopId,
+        within: Option<&OrderedSet<T>>,
+    ) -> Option<&'b T> {
+        self.link_indices(id, real, child)
+            .find(|i| within.is_none_or(|set| set.contains(i)))
+    }
+
+    // The first index at which `child` can be reached from `id` by `query`
+    fn first_index<'b>(
+        &'b self,
+        id: MopId,
+        child: MopId,
+        query: &'b OrderedSet<T>,
+    ) -> Option<&'b T> {
+        self.elements(child)
+            .difference(self.elements(id))
+            .intersection(query.iter())
+            .next()
     }
 
-    fn merged_children(&self) -> RefCell<OrderedMap<T, Rc<Self>>> {
-        let map = (self.children_r.borrow().iter() | self.children_v.borrow().iter()).to_map();
-        RefCell::new(map)
+    // The children of `id` (reached via indices after `k`) paired with the
+    // first index that leads to them.  Only visiting a child via that index
+    // is how algorithms B.8 and B.10 visit each mop once.
+    fn first_index_children<'b>(
+        &'b self,
+        id: MopId,
+        k: Option<&'b T>,
+    ) -> impl Iterator<Item = (&'b T, MopId)> + 'b {
+        let mop = self.mops.get(id);
+        (mop.children_r.iter() | mop.children_v.iter())
+            .filter(move |(j, _)| k.is_none_or(|k| *j > k))
+            .filter(move |(j, child)| {
+                Some(*j) == self.elements(**child).difference(&mop.elements).next()
+            })
+            .map(|(j, child)| (j, *child))
     }
 
-    fn is_recursive_compatible_with(&self, excerpt: &OrderedSet<T>) -> bool {
-        if self.elements().is_subset(excerpt) {
+    fn is_recursive_compatible_with(&self, id: MopId, excerpt: &OrderedSet<T>) -> bool {
+        if self.elements(id).is_subset(excerpt) {
             for key in excerpt.iter() {
-                if let Some(mop) = self.get_r_child(key) {
-                    if !mop.is_recursive_compatible_with(excerpt) {
-                        return false;
-                    }
-                } else if let Some(mop) = self.get_v_child(key) {
-                    if !mop.is_recursive_compatible_with(excerpt) {
+                if let Some(child) = self.get_child(id, key) {
+                    if !self.is_recursive_compatible_with(child, excerpt) {
                         return false;
                     }
                 }
@@ -247,281 +401,277 @@ impl<'a, T: 'a + Ord + Debug + Clone, S: Strength> Mop<T, S> {
         }
         true
     }
+
+    fn new_epitome_from(&mut self, j_mop: MopId, excerpt: &OrderedSet<T>) -> MopId {
+        let mop = self.mops.get(j_mop);
+        let epitome = Mop::new_epitome(
+            mop.elements.intersection(excerpt).to_set(),
+            mop.merged_children(),
+            mop.undif_strength,
+            mop.label_strengths.clone(),
+        );
+        self.mops.add(epitome)
+    }
 }
 
 // Main algorithms
-impl<T: Ord + Debug + Clone, S: Strength> Mop<T, S> {
-    fn algorithm_6_2_interpose(&self, j: &T, excerpt: &OrderedSet<T>) {
-        let (j_mop, j_mop_indices) = self.get_r_child_and_indices(j).unwrap();
-        let m = Self::new_epitome(
-            j_mop.elements.intersection(excerpt).to_set(),
-            j_mop.merged_children(),
-            &j_mop.undif_strength.get(),
-        );
-        m.insert_r_child(j_mop.elements.difference(&m.elements), &j_mop);
-        self.insert_r_child(j_mop_indices.iter(), &m);
+impl<T: Ord + Debug + Clone, S: Strength> RedundantDiscriminationTree<T, S> {
+    fn algorithm_6_2_interpose(&mut self, p: MopId, j: &T, excerpt: &OrderedSet<T>) {
+        let j_mop = self.get_r_child(p, j).unwrap();
+        let j_mop_indices: Vec<T> = self.link_indices(p, true, j_mop).cloned().collect();
+        let m = self.new_epitome_from(j_mop, excerpt);
+        let keys = self.elements(j_mop) - self.elements(m);
+        self.insert_r_child(m, keys.iter(), j_mop);
+        self.insert_r_child(p, j_mop_indices.iter(), m);
     }
 
-    fn algorithm_6_3_split(&self, j: &T, excerpt: &OrderedSet<T>) {
-        let (j_mop, j_mop_indices) = self.get_r_child_and_indices(j).unwrap();
-        let m = Self::new_epitome(
-            j_mop.elements.intersection(excerpt).to_set(),
-            j_mop.merged_children(),
-            &j_mop.undif_strength.get(),
-        );
-        m.insert_v_child(j_mop.elements.difference(&m.elements), &j_mop);
-        self.insert_r_child(excerpt.intersection(&j_mop_indices), &m);
+    fn algorithm_6_3_split(&mut self, p: MopId, j: &T, excerpt: &OrderedSet<T>) {
+        let j_mop = self.get_r_child(p, j).unwrap();
+        let j_mop_indices: Vec<T> = self
+            .link_indices(p, true, j_mop)
+            .filter(|i| excerpt.contains(i))
+            .cloned()
+            .collect();
+        let m = self.new_epitome_from(j_mop, excerpt);
+        let keys = self.elements(j_mop) - self.elements(m);
+        self.insert_v_child(m, keys.iter(), j_mop);
+        self.insert_r_child(p, j_mop_indices.iter(), m);
     }
 
     fn algorithm_6_4_reorganize(
-        &self,
+        &mut self,
+        p: MopId,
         excerpt: &OrderedSet<T>,
-        base_mop: &Rc<Self>,
-        big_u: &mut OrderedSet<(Rc<Self>, Rc<Self>)>,
+        big_u: &mut OrderedSet<(MopId, MopId)>,
     ) {
-        let mut big_a = (excerpt.iter() & self.children_r.borrow().keys()).to_set();
-        while let Some(j) = big_a.first() {
-            let (j_mop, big_i_to) = self.get_r_child_and_indices(j).unwrap();
-            let p = &j_mop;
-            if !excerpt.is_superset(&big_i_to) {
-                self.algorithm_6_3_split(j, excerpt);
-                let j_mop = self.get_r_child(j).unwrap();
-                j_mop.algorithm_6_9_fix_v_links(big_u);
-                base_mop.algorithm_6_10_fix_v_links(&(Rc::clone(p), Rc::clone(&j_mop)));
-                big_u.insert((Rc::clone(p), j_mop));
-            } else if !excerpt.is_superset(&j_mop.elements.difference(&self.elements).to_set()) {
-                self.algorithm_6_2_interpose(j, excerpt);
-                let j_mop = self.get_r_child(j).unwrap();
-                j_mop.algorithm_6_9_fix_v_links(big_u);
-                base_mop.algorithm_6_10_fix_v_links(&(Rc::clone(p), Rc::clone(&j_mop)));
-                big_u.insert((Rc::clone(p), j_mop));
+        let big_a: Vec<T> = (excerpt.iter() & self.mops.get(p).children_r.keys())
+            .cloned()
+            .collect();
+        for j in big_a.iter() {
+            let j_mop = match self.get_r_child(p, j) {
+                Some(j_mop) if self.first_link_index(p, true, j_mop, Some(excerpt)) == Some(j) => {
+                    j_mop
+                }
+                _ => continue,
+            };
+            if !self
+                .link_indices(p, true, j_mop)
+                .all(|i| excerpt.contains(i))
+            {
+                self.algorithm_6_3_split(p, j, excerpt);
+                let new_j_mop = self.get_r_child(p, j).unwrap();
+                self.algorithm_6_9_fix_v_links(new_j_mop, big_u);
+                self.algorithm_6_10_fix_v_links(MopId::ROOT, (j_mop, new_j_mop));
+                big_u.insert((j_mop, new_j_mop));
+            } else if !self
+                .elements(j_mop)
+                .difference(self.elements(p))
+                .all(|e| excerpt.contains(e))
+            {
+                self.algorithm_6_2_interpose(p, j, excerpt);
+                let new_j_mop = self.get_r_child(p, j).unwrap();
+                self.algorithm_6_9_fix_v_links(new_j_mop, big_u);
+                self.algorithm_6_10_fix_v_links(MopId::ROOT, (j_mop, new_j_mop));
+                big_u.insert((j_mop, new_j_mop));
             } else {
-                j_mop.algorithm_6_4_reorganize(excerpt, base_mop, big_u);
+                self.algorithm_6_4_reorganize(j_mop, excerpt, big_u);
             }
-
-            big_a -= big_i_to;
         }
     }
 
-    fn algorithm_6_6_interpose(&self, j: &T, excerpt: &OrderedSet<T>) {
-        let (j_mop_v, j_mop_v_indices) = self.get_v_child_and_indices(j).unwrap();
-        let m = Self::new_epitome(
-            j_mop_v.elements.intersection(excerpt).to_set(),
-            j_mop_v.merged_children(),
-            &j_mop_v.undif_strength.get(),
-        );
-        m.insert_v_child(j_mop_v.elements.difference(&m.elements), &j_mop_v);
-        self.insert_r_child(excerpt.intersection(&j_mop_v_indices), &m);
-        self.delete_v_children(excerpt.intersection(&j_mop_v_indices));
+    fn algorithm_6_6_interpose(&mut self, p: MopId, j: &T, excerpt: &OrderedSet<T>) {
+        let j_mop_v = self.get_v_child(p, j).unwrap();
+        let j_mop_v_indices: Vec<T> = self
+            .link_indices(p, false, j_mop_v)
+            .filter(|i| excerpt.contains(i))
+            .cloned()
+            .collect();
+        let m = self.new_epitome_from(j_mop_v, excerpt);
+        let keys = self.elements(j_mop_v) - self.elements(m);
+        self.insert_v_child(m, keys.iter(), j_mop_v);
+        self.insert_r_child(p, j_mop_v_indices.iter(), m);
+        self.delete_v_children(p, j_mop_v_indices.iter());
     }
 
     fn algorithm_6_7_reorganize(
-        &self,
+        &mut self,
+        p: MopId,
         excerpt: &OrderedSet<T>,
-        base_mop: &Rc<Self>,
-        big_u: &mut OrderedSet<(Rc<Self>, Rc<Self>)>,
+        big_u: &mut OrderedSet<(MopId, MopId)>,
     ) {
-        let mut big_a_v = (excerpt.iter() & self.children_v.borrow().keys()).to_set();
-        while let Some(j) = big_a_v.first() {
-            let (j_mop_v, j_mop_v_indices) = self.get_v_child_and_indices(j).unwrap();
-            if excerpt.is_superset(&j_mop_v.elements) {
-                big_a_v -= j_mop_v_indices;
-            } else {
-                self.algorithm_6_6_interpose(j, excerpt);
-                let (j_mop, j_mop_indices) = self.get_r_child_and_indices(j).unwrap();
-                j_mop.algorithm_6_9_fix_v_links(big_u);
-                base_mop.algorithm_6_10_fix_v_links(&(Rc::clone(&j_mop_v), Rc::clone(&j_mop)));
-                big_u.insert((Rc::clone(&j_mop_v), Rc::clone(&j_mop)));
-                big_a_v -= j_mop_indices;
+        let big_a_v: Vec<T> = (excerpt.iter() & self.mops.get(p).children_v.keys())
+            .cloned()
+            .collect();
+        for j in big_a_v.iter() {
+            // NB: interposing moves the indices in `excerpt` to real links
+            let j_mop_v = match self.get_v_child(p, j) {
+                Some(j_mop_v)
+                    if self.first_link_index(p, false, j_mop_v, Some(excerpt)) == Some(j) =>
+                {
+                    j_mop_v
+                }
+                _ => continue,
+            };
+            if !excerpt.is_superset(self.elements(j_mop_v)) {
+                self.algorithm_6_6_interpose(p, j, excerpt);
+                let j_mop = self.get_r_child(p, j).unwrap();
+                self.algorithm_6_9_fix_v_links(j_mop, big_u);
+                self.algorithm_6_10_fix_v_links(MopId::ROOT, (j_mop_v, j_mop));
+                big_u.insert((j_mop_v, j_mop));
             }
         }
-        let mut big_a = (excerpt.iter() & self.children_r.borrow().keys()).to_set();
-        while let Some(j) = big_a.first() {
-            let (j_mop, j_mop_indices) = self.get_r_child_and_indices(j).unwrap();
-            j_mop.algorithm_6_7_reorganize(excerpt, base_mop, big_u);
-            big_a -= j_mop_indices;
+        let big_a: Vec<T> = (excerpt.iter() & self.mops.get(p).children_r.keys())
+            .cloned()
+            .collect();
+        for j in big_a.iter() {
+            match self.get_r_child(p, j) {
+                Some(j_mop) if self.first_link_index(p, true, j_mop, Some(excerpt)) == Some(j) => {
+                    self.algorithm_6_7_reorganize(j_mop, excerpt, big_u);
+                }
+                _ => (),
+            }
         }
     }
 
-    fn algorithm_6_9_fix_v_links(&self, big_u: &OrderedSet<(Rc<Self>, Rc<Self>)>) {
+    fn algorithm_6_9_fix_v_links(&mut self, id: MopId, big_u: &OrderedSet<(MopId, MopId)>) {
         for (m1, m2) in big_u.iter() {
-            if m2.elements.is_superset(&self.elements) {
-                for k in m2.elements.iter() {
-                    if let Some(k_mop_v) = self.get_v_child(k) {
-                        if k_mop_v == *m1 {
-                            self.children_v
-                                .borrow_mut()
-                                .insert(k.clone(), Rc::clone(m2));
-                        }
-                    }
+            if self.elements(*m2).is_superset(self.elements(id)) {
+                let keys: Vec<T> = self
+                    .elements(*m2)
+                    .iter()
+                    .filter(|k| self.get_v_child(id, k) == Some(*m1))
+                    .cloned()
+                    .collect();
+                let children_v = &mut self.mops.get_mut(id).children_v;
+                for k in keys {
+                    children_v.insert(k, *m2);
                 }
             }
         }
     }
 
-    fn algorithm_6_10_fix_v_links(&self, mops: &(Rc<Self>, Rc<Self>)) {
-        if mops.1.elements.is_superset(&self.elements) {
-            let big_c_r = mops.1.elements.difference(&self.elements).to_set();
+    fn algorithm_6_10_fix_v_links(&mut self, id: MopId, mops: (MopId, MopId)) {
+        if self.elements(mops.1).is_superset(self.elements(id)) {
+            let big_c_r = self.elements(mops.1) - self.elements(id);
             for k in big_c_r.iter() {
-                if let Some(mop_k_v) = self.get_v_child(k) {
-                    if mop_k_v == mops.0 {
-                        self.children_v
-                            .borrow_mut()
-                            .insert(k.clone(), Rc::clone(&mops.1));
-                    }
+                if self.get_v_child(id, k) == Some(mops.0) {
+                    self.mops.get_mut(id).children_v.insert(k.clone(), mops.1);
                 }
             }
-            let mut big_a = (big_c_r.iter() & self.children_r.borrow().keys()).to_set();
-            while let Some(j) = big_a.first() {
-                let (j_mop, j_mop_indices) = self.get_r_child_and_indices(j).unwrap();
-                j_mop.algorithm_6_10_fix_v_links(mops);
-                big_a -= j_mop_indices;
+            let big_a: Vec<T> = (big_c_r.iter() & self.mops.get(id).children_r.keys())
+                .cloned()
+                .collect();
+            for j in big_a.iter() {
+                match self.get_r_child(id, j) {
+                    Some(j_mop)
+                        if self.first_link_index(id, true, j_mop, Some(&big_c_r)) == Some(j) =>
+                    {
+                        self.algorithm_6_10_fix_v_links(j_mop, mops);
+                    }
+                    _ => (),
+                }
             }
         }
     }
 
-    fn algorithm_6_12_decr_strengths(&self) {
-        self.decr_trace_strength();
-        self.decr_epitome_strength();
-        self.decr_undif_strength();
-        let mut big_a = self.children_r.borrow().keys().to_set();
-        while let Some(j) = big_a.first() {
-            let (child, indices) = self.get_r_child_and_indices(j).unwrap();
-            child.algorithm_6_12_decr_strengths();
-            big_a -= indices;
-        }
-    }
-}
-
-trait Engine<T: Ord + Debug + Clone, S: Strength> {
-    fn algorithm_6_11_absorb(&self, excerpt: &OrderedSet<T>, new_trace: &mut Option<Rc<Mop<T, S>>>);
-    fn algorithm_6_13_complete_match(&self, query: &OrderedSet<T>) -> Option<Rc<Mop<T, S>>>;
-    fn algorithm_6_14_partial_match(&self, query: &OrderedSet<T>) -> OrderedSet<Rc<Mop<T, S>>>;
-    fn algorithm_6_15_partial_match_after(
-        &self,
-        query: &OrderedSet<T>,
-        k: &T,
-    ) -> OrderedSet<Rc<Mop<T, S>>>;
-    fn algorithm_b8_mod_traces_after(&self, after: &T) -> OrderedSet<Rc<Mop<T, S>>>;
-    fn algorithm_b10_mod_epitomes_after(&self, k: &T) -> OrderedSet<Rc<Mop<T, S>>>;
-}
-
-impl<T: Ord + Debug + Clone, S: Strength> Engine<T, S> for Rc<Mop<T, S>> {
     fn algorithm_6_11_absorb(
-        &self,
+        &mut self,
+        id: MopId,
         excerpt: &OrderedSet<T>,
-        new_trace: &mut Option<Rc<Mop<T, S>>>,
+        weight: f64,
+        label: Option<&str>,
+        new_trace: &mut Option<MopId>,
     ) {
-        let big_x_u = excerpt - &self.elements;
+        let (now, config) = (self.clock, self.config);
+        let big_x_u = excerpt - self.elements(id);
         if big_x_u.is_empty() {
-            *new_trace = Some(Rc::clone(self));
-            self.incr_trace_strength();
+            *new_trace = Some(id);
+            self.mops
+                .get_mut(id)
+                .trace_strength
+                .increase_at(weight, now, &config);
         } else {
-            let mut big_a = (big_x_u.iter() & self.children_r.borrow().keys()).to_set();
-            while let Some(j) = big_a.first() {
-                let (j_mop, j_mop_indices) = self.get_r_child_and_indices(j).unwrap();
-                j_mop.algorithm_6_11_absorb(excerpt, new_trace);
-                big_a -= j_mop_indices;
-            }
-            let temp_set = ((big_x_u.iter() - self.children_r.borrow().keys())
-                - self.children_v.borrow().keys())
-            .to_set();
+            let big_a: Vec<T> = (big_x_u.iter() & self.mops.get(id).children_r.keys())
+                .cloned()
+                .collect();
+            for j in big_a.iter() {
+                match self.get_r_child(id, j) {
+                    Some(j_mop)
+                        if self.first_link_index(id, true, j_mop, Some(&big_x_u)) == Some(j) =>
+                    {
+                        self.algorithm_6_11_absorb(j_mop, excerpt, weight, label, new_trace);
+                    }
+                    _ => (),
+                }
+            }
+            let mop = self.mops.get(id);
+            let temp_set: Vec<T> = ((big_x_u.iter() - mop.children_r.keys())
+                - mop.children_v.keys())
+            .cloned()
+            .collect();
             if !temp_set.is_empty() {
-                if let Some(p) = new_trace {
-                    self.insert_v_child(temp_set.iter(), p);
+                if let Some(p) = *new_trace {
+                    self.insert_v_child(id, temp_set.iter(), p);
                 } else {
-                    let p = Mop::<T, S>::new_trace(excerpt.clone());
-                    self.insert_r_child(temp_set.iter(), &p);
+                    let p =
+                        self.mops
+                            .add(Mop::new_trace(excerpt.clone(), weight, now, &config, label));
+                    self.insert_r_child(id, temp_set.iter(), p);
                     *new_trace = Some(p);
                 }
             }
-            self.incr_epitome_strength();
+            self.mops
+                .get_mut(id)
+                .epitome_strength
+                .increase_at(weight, now, &config);
         }
-        self.incr_undif_strength();
+        let mop = self.mops.get_mut(id);
+        mop.undif_strength.increase_at(weight, now, &config);
+        mop.incr_label_strength(label, weight, now, &config);
     }
 
-    fn algorithm_6_13_complete_match(&self, query: &OrderedSet<T>) -> Option<Rc<Mop<T, S>>> {
-        let mut p = Rc::clone(self);
-        let mut big_j = query - &self.elements;
+    // Every mop in the arena is in the tree so there is no need to walk it
+    fn algorithm_6_12_decr_strengths(&mut self) {
+        let config = self.config;
+        for id in self.mops.ids() {
+            self.mops.get_mut(id).decr_strengths(&config);
+        }
+    }
+
+    fn algorithm_6_13_complete_match(&self, query: &OrderedSet<T>) -> Option<MopId> {
+        let mut p = MopId::ROOT;
+        let mut big_j = query - self.elements(p);
         while let Some(j) = big_j.first() {
-            if let Some(j_mop) = p.get_r_child(j) {
-                p = j_mop;
-                big_j = big_j.difference(&p.elements).to_set();
-            } else if let Some(j_mop) = p.get_v_child(j) {
-                p = j_mop;
-                big_j = big_j.difference(&p.elements).to_set();
-            } else {
-                return None;
-            }
+            p = self.get_child(p, j)?;
+            big_j = big_j.difference(self.elements(p)).to_set();
         }
         Some(p)
     }
 
-    fn algorithm_6_14_partial_match(&self, query: &OrderedSet<T>) -> OrderedSet<Rc<Mop<T, S>>> {
-        let mut big_s = OrderedSet::default();
-        if self.is_disjoint_child_indices(query) {
-            if !query.is_disjoint(self.elements()) {
-                big_s.insert(Rc::clone(self));
-            }
-        } else {
-            for j in query.difference(self.elements()) {
-                if let Some(j_mop) = self.get_r_child(j) {
-                    if j == j_mop
-                        .elements()
-                        .difference(self.elements())
-                        .intersection(query.iter())
-                        .next()
-                        .unwrap()
-                    {
-                        big_s |= j_mop.algorithm_6_15_partial_match_after(query, j);
-                    }
-                } else if let Some(j_mop) = self.get_v_child(j) {
-                    if j == j_mop
-                        .elements()
-                        .difference(self.elements())
-                        .intersection(query.iter())
-                        .next()
-                        .unwrap()
-                    {
-                        big_s |= j_mop.algorithm_6_15_partial_match_after(query, j);
-                    }
-                }
-            }
-        }
-        big_s
+    fn algorithm_6_14_partial_match(&self, query: &OrderedSet<T>) -> OrderedSet<MopRef<'_, T, S>> {
+        self.algorithm_6_15_partial_match_after(MopId::ROOT, query, None)
     }
 
     fn algorithm_6_15_partial_match_after(
         &self,
+        id: MopId,
         query: &OrderedSet<T>,
-        k: &T,
-    ) -> OrderedSet<Rc<Mop<T, S>>> {
+        k: Option<&T>,
+    ) -> OrderedSet<MopRef<'_, T, S>> {
         let mut big_s = OrderedSet::default();
-        if self.is_disjoint_child_indices(query) {
-            if !query.is_disjoint(self.elements()) {
-                big_s.insert(Rc::clone(self));
+        let mop = self.mops.get(id);
+        if mop.is_disjoint_child_indices(query) {
+            if !query.is_disjoint(&mop.elements) {
+                big_s.insert(self.mop(id));
             }
         } else {
-            for j in query.difference(self.elements()).advance_past(k) {
-                if let Some(j_mop) = self.get_r_child(j) {
-                    if j == j_mop
-                        .elements()
-                        .difference(self.elements())
-                        .intersection(query.iter())
-                        .next()
-                        .unwrap()
-                    {
-                        big_s |= j_mop.algorithm_6_15_partial_match_after(query, j);
-                    }
-                } else if let Some(j_mop) = self.get_v_child(j) {
-                    if j == j_mop
-                        .elements()
-                        .difference(self.elements())
-                        .intersection(query.iter())
-                        .next()
-                        .unwrap()
-                    {
-                        big_s |= j_mop.algorithm_6_15_partial_match_after(query, j);
+            for j in query.difference(&mop.elements) {
+                if k.is_some_and(|k| j <= k) {
+                    continue;
+                }
+                if let Some(j_mop) = self.get_child(id, j) {
+                    if Some(j) == self.first_index(id, j_mop, query) {
+                        big_s |= self.algorithm_6_15_partial_match_after(j_mop, query, Some(j));
                     }
                 }
             }
@@ -529,93 +679,364 @@ impl<T: Ord + Debug + Clone, S: Strength> Engine<T, S> for Rc<Mop<T, S>> {
         big_s
     }
 
-    fn algorithm_b8_mod_traces_after(&self, k: &T) -> OrderedSet<Rc<Mop<T, S>>> {
+    fn algorithm_b8_mod_traces_after(
+        &self,
+        id: MopId,
+        k: Option<&T>,
+    ) -> OrderedSet<MopRef<'_, T, S>> {
         let mut big_s = OrderedSet::default();
-        if self.is_trace() {
-            big_s.insert(Rc::clone(self));
+        if self.mops.get(id).is_trace() {
+            big_s.insert(self.mop(id));
         }
-        for (j, j_mop) in
-            (self.children_r.borrow().iter() | self.children_v.borrow().iter()).advance_past_key(k)
-        {
-            if j == j_mop.elements().difference(self.elements()).next().unwrap() {
-                big_s |= j_mop.algorithm_b8_mod_traces_after(j);
-            }
+        for (j, j_mop) in self.first_index_children(id, k) {
+            big_s |= self.algorithm_b8_mod_traces_after(j_mop, Some(j));
         }
         big_s
     }
 
-    fn algorithm_b10_mod_epitomes_after(&self, k: &T) -> OrderedSet<Rc<Mop<T, S>>> {
+    fn algorithm_b10_mod_epitomes_after(
+        &self,
+        id: MopId,
+        k: Option<&T>,
+    ) -> OrderedSet<MopRef<'_, T, S>> {
         let mut big_s = OrderedSet::default();
-        if self.is_epitome() {
-            big_s.insert(Rc::clone(self));
+        if self.mops.get(id).is_epitome() {
+            big_s.insert(self.mop(id));
         }
-        for (j, j_mop) in
-            (self.children_r.borrow().iter() | self.children_v.borrow().iter()).advance_past_key(k)
-        {
-            if j == j_mop.elements().difference(self.elements()).next().unwrap() {
-                big_s |= j_mop.algorithm_b10_mod_epitomes_after(j);
-            }
+        for (j, j_mop) in self.first_index_children(id, k) {
+            big_s |= self.algorithm_b10_mod_epitomes_after(j_mop, Some(j));
         }
         big_s
     }
+
+    // All the mops of the tree in element order.
+    pub(crate) fn all_mops(&self) -> OrderedSet<MopRef<'_, T, S>> {
+        let mops: Vec<MopRef<'_, T, S>> = self.mops.ids().map(|id| self.mop(id)).collect();
+        mops.into()
+    }
+
+    // Which mops can be reached from the root
+    fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.mops.len()];
+        let mut stack = vec![MopId::ROOT];
+        while let Some(id) = stack.pop() {
+            if !reachable[id.index()] {
+                reachable[id.index()] = true;
+                let mop = self.mops.get(id);
+                for (_, child) in mop.children_r.iter().chain(mop.children_v.iter()) {
+                    stack.push(*child);
+                }
+            }
+        }
+        reachable
+    }
+
+    // Children always have more elements than their parents so visiting
+    // mops largest first means that a mop's children have been dealt with
+    // by the time that its own links are fixed.  The mops that are no
+    // longer reachable are then discarded from the arena.
+    fn tidy(&mut self) {
+        let mut ids: Vec<MopId> = self.mops.ids().collect();
+        ids.sort_by_key(|id| std::cmp::Reverse(self.elements(*id).len()));
+        let mut fates = OrderedMap::<MopId, Fate>::new();
+        for id in ids {
+            self.fix_links(id, &fates);
+            if id != MopId::ROOT {
+                if let Some(fate) = self.fate(id) {
+                    fates.insert(id, fate);
+                }
+            }
+        }
+        let reachable = self.reachable();
+        self.mops.retain(|id| reachable[id.index()]);
+    }
 }
 
-pub trait Public<T: Ord + Debug + Clone, S: Strength> {
-    fn traces(&self) -> OrderedSet<Rc<Mop<T, S>>>;
-    fn epitomes(&self) -> OrderedSet<Rc<Mop<T, S>>>;
+// Retracting
+impl<T: Ord + Debug + Clone, S: Strength> RedundantDiscriminationTree<T, S> {
+    // The label strengths that the trace `id` got from the inclusions of
+    // its own excerpt rather than those of the traces that are supersets
+    // of it (which are all below it).
+    fn own_label_strengths(&self, id: MopId) -> OrderedMap<String, S> {
+        let (now, config) = (self.clock, self.config);
+        let mut traces: Vec<MopId> = self.mop(id).traces().iter().map(MopRef::id).collect();
+        traces.sort_by_key(|trace| std::cmp::Reverse(self.elements(*trace).len()));
+        let mut owns: Vec<(MopId, OrderedMap<String, S>)> = vec![];
+        for trace in traces {
+            let mut own = OrderedMap::<String, S>::new();
+            for (label, strength) in self.mops.get(trace).label_strengths.iter() {
+                let mut strength = strength.rebased(now, now, &config);
+                for (superset, superset_own) in owns.iter() {
+                    if self.elements(*superset).is_superset(self.elements(trace)) {
+                        if let Some(other) = superset_own.get(label) {
+                            strength = strength.without(other);
+                        }
+                    }
+                }
+                own.insert(label.clone(), strength);
+            }
+            owns.push((trace, own));
+        }
+        owns.pop().map(|(_, own)| own).unwrap_or_default()
+    }
+
+    // Undo what algorithm 6.11 did to the strengths of the mops that are
+    // subsets of the trace `id` for each inclusion of its excerpt.  These
+    // are the mops that algorithm 6.11 visits but, as the tree is redundant,
+    // a walk from the root would reach many of them by more than one path so
+    // the arena is scanned instead to visit each of them exactly once.
+    fn retract(&mut self, id: MopId) {
+        let (now, config) = (self.clock, self.config);
+        let at_now = |strength: &S| strength.rebased(now, now, &config);
+        let trace_strength = at_now(&self.mops.get(id).trace_strength);
+        let label_strengths = self.own_label_strengths(id);
+        let excerpt = self.elements(id).clone();
+        let subsets: Vec<MopId> = self
+            .mops
+            .ids()
+            .filter(|other| self.elements(*other).is_subset(&excerpt))
+            .collect();
+        for other in subsets {
+            let mop = self.mops.get_mut(other);
+            if other != id {
+                mop.epitome_strength = at_now(&mop.epitome_strength).without(&trace_strength);
+            }
+            mop.undif_strength = at_now(&mop.undif_strength).without(&trace_strength);
+            for (label, retracted) in label_strengths.iter() {
+                if let Some(strength) = mop.label_strengths.get_mut(label) {
+                    *strength = at_now(strength).without(retracted);
+                    if strength.value() <= 0.0 {
+                        mop.label_strengths.remove(label);
+                    }
+                }
+            }
+        }
+    }
 }
 
-impl<T: Ord + Debug + Clone, S: Strength> Public<T, S> for Rc<Mop<T, S>> {
-    fn traces(&self) -> OrderedSet<Rc<Mop<T, S>>> {
-        let mut big_s = OrderedSet::default();
-        if self.is_trace() {
-            big_s.insert(Rc::clone(self));
+// Forgetting
+#[derive(Clone, Copy)]
+enum Fate {
+    Removed,
+    // The bool records whether the replacement was a real child of the
+    // mop that it replaces.
+    Replaced(MopId, bool),
+}
+
+impl<T: Ord + Debug + Clone, S: Strength> Mop<T, S> {
+    fn is_below(&self, threshold: f64, now: u64, config: &StrengthConfig) -> bool {
+        self.trace_strength.value_at(now, config) < threshold
+            && self.epitome_strength.value_at(now, config) < threshold
+            && self.undif_strength.value_at(now, config) < threshold
+    }
+
+    fn forget_trace(&mut self) {
+        self.trace_strength = S::default();
+    }
+}
+
+impl<T: Ord + Debug + Clone, S: Strength> RedundantDiscriminationTree<T, S> {
+    // Turn the virtual links from `id` to `child` into real links.
+    fn adopt(&mut self, id: MopId, child: MopId) {
+        let mop = self.mops.get_mut(id);
+        let keys: Vec<T> = mop
+            .children_v
+            .iter()
+            .filter(|(_, c)| **c == child)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for k in keys {
+            mop.children_v.remove(&k);
+            mop.children_r.insert(k, child);
         }
-        for (j, j_mop) in self.children_r.borrow().iter() | self.children_v.borrow().iter() {
-            if j == j_mop.elements().difference(self.elements()).next().unwrap() {
-                big_s |= j_mop.algorithm_b8_mod_traces_after(j);
+    }
+
+    fn fix_links(&mut self, id: MopId, fates: &OrderedMap<MopId, Fate>) {
+        let mop = self.mops.get_mut(id);
+        let changes: Vec<(T, Fate)> = mop
+            .children_r
+            .iter()
+            .filter_map(|(k, child)| fates.get(child).map(|fate| (k.clone(), *fate)))
+            .collect();
+        for (k, fate) in changes {
+            match fate {
+                Fate::Removed => {
+                    mop.children_r.remove(&k);
+                }
+                Fate::Replaced(child, true) => {
+                    mop.children_r.insert(k, child);
+                }
+                Fate::Replaced(child, false) => {
+                    mop.children_r.remove(&k);
+                    mop.children_v.insert(k, child);
+                }
+            }
+        }
+        let changes: Vec<(T, Fate)> = mop
+            .children_v
+            .iter()
+            .filter_map(|(k, child)| fates.get(child).map(|fate| (k.clone(), *fate)))
+            .collect();
+        for (k, fate) in changes {
+            match fate {
+                Fate::Removed => {
+                    mop.children_v.remove(&k);
+                }
+                Fate::Replaced(child, _) => {
+                    mop.children_v.insert(k, child);
+                }
             }
         }
-        big_s
     }
 
-    fn epitomes(&self) -> OrderedSet<Rc<Mop<T, S>>> {
-        let mut big_s = OrderedSet::default();
-        if self.is_epitome() {
-            big_s.insert(Rc::clone(self));
+    // A mop that is not a trace is only needed if it leads to more than one
+    // mop none of which contains all of the others.  If one child is a
+    // subset of all the others then every trace that contains our elements
+    // also contains that child's elements and the child can replace us.
+    fn fate(&mut self, id: MopId) -> Option<Fate> {
+        if self.mops.get(id).is_trace() {
+            return None;
         }
-        for (j, j_mop) in self.children_r.borrow().iter() | self.children_v.borrow().iter() {
-            if j == j_mop.elements().difference(self.elements()).next().unwrap() {
-                big_s |= j_mop.algorithm_b10_mod_epitomes_after(j);
+        let children: Vec<MopId> = self
+            .mop(id)
+            .distinct_children()
+            .iter()
+            .map(MopRef::id)
+            .collect();
+        let smallest = match children
+            .iter()
+            .min_by_key(|child| self.elements(**child).len())
+        {
+            Some(child) => *child,
+            None => return Some(Fate::Removed),
+        };
+        if children
+            .iter()
+            .all(|child| self.elements(*child).is_superset(self.elements(smallest)))
+        {
+            let real_children: Vec<MopId> = self
+                .mops
+                .get(id)
+                .children_r
+                .iter()
+                .map(|(_, child)| *child)
+                .filter(|child| *child != smallest)
+                .collect();
+            for child in real_children {
+                self.adopt(smallest, child);
             }
+            let is_real = self
+                .mops
+                .get(id)
+                .children_r
+                .iter()
+                .any(|(_, child)| *child == smallest);
+            Some(Fate::Replaced(smallest, is_real))
+        } else {
+            None
         }
-        big_s
     }
 }
 
-#[derive(Debug, Default)]
-pub struct RedundantDiscriminationTree<T: Ord + Debug + Clone, S: Strength> {
-    mop: Rc<Mop<T, S>>,
+impl<T: Ord + Debug + Clone, S: Strength> Default for RedundantDiscriminationTree<T, S> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T: Ord + Debug + Clone, S: Strength> RedundantDiscriminationTree<T, S> {
     pub fn new() -> Self {
+        Self::with_config(StrengthConfig::of::<S>())
+    }
+
+    /// A tree whose strengths grow and decay at the rates in `config`
+    /// rather than those given by `S`'s associated constants.  Panics if
+    /// `config` is not valid or if `S` does not use the config and `config`
+    /// differs from its rates.
+    pub fn with_config(config: StrengthConfig) -> Self {
+        if S::USES_CONFIG {
+            assert!(
+                config.is_valid(),
+                "rates must be between 0.0 and 1.0 and growth must be above 0.0"
+            );
+        } else {
+            assert!(
+                config == StrengthConfig::of::<S>(),
+                "this strength only supports its own rates"
+            );
+        }
         Self {
-            mop: Mop::<T, S>::tabula_rasa(),
+            mops: Arena::new(Mop::default()),
+            config,
+            clock: 0,
         }
     }
 
+    pub fn config(&self) -> &StrengthConfig {
+        &self.config
+    }
+
+    /// The mop with the given id.  Ids are only valid until the tree is
+    /// pruned, has an excerpt excluded or is merged with another tree.
+    pub fn mop(&self, id: MopId) -> MopRef<'_, T, S> {
+        assert!(id.index() < self.mops.len(), "no such mop");
+        MopRef { tree: self, id }
+    }
+
     // Algorithm 6.1
     pub fn include_excerpt(&mut self, excerpt: OrderedSet<T>) {
-        let mut big_u = OrderedSet::<(Rc<Mop<T, S>>, Rc<Mop<T, S>>)>::new();
-        let mut new_trace: Option<Rc<Mop<T, S>>> = None;
-        self.mop
-            .algorithm_6_4_reorganize(&excerpt, &self.mop, &mut big_u);
-        self.mop
-            .algorithm_6_7_reorganize(&excerpt, &self.mop, &mut big_u);
-        assert!(self.mop.is_recursive_compatible_with(&excerpt));
-        self.mop.algorithm_6_11_absorb(&excerpt, &mut new_trace);
-        assert!(self.mop.verify_tree());
+        self.include_weighted_excerpt(excerpt, 1.0);
+    }
+
+    /// Include `excerpt` as if it had been experienced `weight` times (which
+    /// need not be a whole number) in a row.  Panics if `weight` is not
+    /// positive and finite or is too small to increase `S` at all (e.g. less
+    /// than 0.5 for strengths that round weights to whole increases).
+    pub fn include_weighted_excerpt(&mut self, excerpt: OrderedSet<T>, weight: f64) {
+        self.include(excerpt, weight, None);
+    }
+
+    /// Include `excerpt` as an example of the class `label`.  As well as the
+    /// usual strengths every mop whose elements are a subset of `excerpt`
+    /// has its strength for `label` increased.
+    pub fn include_labelled_excerpt(&mut self, excerpt: OrderedSet<T>, label: &str) {
+        self.include(excerpt, 1.0, Some(label));
+    }
+
+    fn include(&mut self, excerpt: OrderedSet<T>, weight: f64, label: Option<&str>) {
+        self.reorganize_and_absorb(&excerpt, weight, label);
+        assert!(self.verify_tree(MopId::ROOT));
+    }
+
+    // Panics (before the tree is changed) if `weight` is not a valid weight
+    // for an excerpt.  Strengths that round weights would otherwise create
+    // mops with no strength for weights that round to zero.
+    fn check_weight(&self, weight: f64) {
+        assert!(
+            weight.is_finite() && weight > 0.0,
+            "excerpt weights must be positive and finite"
+        );
+        let strength = S::new(false).incremented_at(weight, self.clock, &self.config);
+        assert!(
+            strength.value_at(self.clock, &self.config) > 0.0,
+            "excerpt weight {weight} is too small to increase the strengths"
+        );
+        // NB: every inclusion increases the root's undif strength so it is
+        // the strength that overflows first (e.g. for counts).
+        self.mops
+            .get(MopId::ROOT)
+            .undif_strength
+            .incremented_at(weight, self.clock, &self.config);
+    }
+
+    fn reorganize_and_absorb(&mut self, excerpt: &OrderedSet<T>, weight: f64, label: Option<&str>) {
+        self.check_weight(weight);
+        let mut big_u = OrderedSet::<(MopId, MopId)>::new();
+        let mut new_trace: Option<MopId> = None;
+        self.algorithm_6_4_reorganize(MopId::ROOT, excerpt, &mut big_u);
+        self.algorithm_6_7_reorganize(MopId::ROOT, excerpt, &mut big_u);
+        assert!(self.is_recursive_compatible_with(MopId::ROOT, excerpt));
+        self.algorithm_6_11_absorb(MopId::ROOT, excerpt, weight, label, &mut new_trace);
     }
 
     pub fn include_experience(&mut self, experience: &[T]) {
@@ -623,24 +1044,123 @@ impl<T: Ord + Debug + Clone, S: Strength> RedundantDiscriminationTree<T, S> {
         self.include_excerpt(excerpt);
     }
 
+    /// Include all of `excerpts` collapsing duplicates: each distinct
+    /// excerpt is reorganized and absorbed once, with its number of
+    /// occurrences as its weight, and the tree is only verified once at the
+    /// end.  Only identical excerpts are collapsed so this only saves work
+    /// when excerpts repeat.  The resulting traces and epitomes are the same
+    /// as those of sequential inclusion as are their strengths (within
+    /// rounding error).
+    pub fn include_excerpts<I: IntoIterator<Item = OrderedSet<T>>>(&mut self, excerpts: I) {
+        let mut excerpts: Vec<OrderedSet<T>> = excerpts.into_iter().collect();
+        excerpts.sort_unstable();
+        for duplicates in excerpts.chunk_by(|a, b| a == b) {
+            self.reorganize_and_absorb(&duplicates[0], duplicates.len() as f64, None);
+        }
+        assert!(self.verify_tree(MopId::ROOT));
+    }
+
+    /// Age the tree by one tick.  Strengths that decay lazily are decayed
+    /// by the tree's clock as they are read so only the others have to be
+    /// decremented.
     pub fn decrement_strengths(&mut self) {
-        self.mop.algorithm_6_12_decr_strengths();
+        self.clock += 1;
+        if !S::DECAYS_LAZILY {
+            self.algorithm_6_12_decr_strengths();
+        }
     }
 
-    pub fn complete_match(&self, query: &OrderedSet<T>) -> Option<Rc<Mop<T, S>>> {
-        self.mop.algorithm_6_13_complete_match(query)
+    /// Forget traces whose trace, epitome and undif strengths have all
+    /// decayed below `threshold` and discard the mops that are no longer
+    /// needed to reach the remaining traces.
+    pub fn prune(&mut self, threshold: f64) {
+        let ids: Vec<MopId> = self.mops.ids().collect();
+        for id in ids {
+            if self
+                .mops
+                .get(id)
+                .is_below(threshold, self.clock, &self.config)
+            {
+                self.mops.get_mut(id).forget_trace();
+            }
+        }
+        self.tidy();
+        assert!(self.verify_tree(MopId::ROOT));
+    }
+
+    /// Retract `excerpt` from the tree (no matter how many times it has been
+    /// included), taking its contribution off the strengths of the mops
+    /// that are subsets of it, and merge away any epitomes that were only
+    /// needed to discriminate it from the other traces.  Returns `false` if
+    /// `excerpt` is not a trace in the tree.
+    pub fn exclude_excerpt(&mut self, excerpt: &OrderedSet<T>) -> bool {
+        match self.algorithm_6_13_complete_match(excerpt) {
+            Some(id) if self.elements(id) == excerpt && self.mops.get(id).is_trace() => {
+                self.retract(id);
+                self.mops.get_mut(id).forget_trace();
+                self.tidy();
+                assert!(self.verify_tree(MopId::ROOT));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn complete_match(&self, query: &OrderedSet<T>) -> Option<MopRef<'_, T, S>> {
+        self.algorithm_6_13_complete_match(query)
+            .map(|id| self.mop(id))
     }
 
-    pub fn partial_matches(&self, query: &OrderedSet<T>) -> OrderedSet<Rc<Mop<T, S>>> {
-        self.mop.algorithm_6_14_partial_match(query)
+    pub fn partial_matches(&self, query: &OrderedSet<T>) -> OrderedSet<MopRef<'_, T, S>> {
+        self.algorithm_6_14_partial_match(query)
     }
 
-    pub fn traces(&self) -> OrderedSet<Rc<Mop<T, S>>> {
-        self.mop.traces()
+    pub fn traces(&self) -> OrderedSet<MopRef<'_, T, S>> {
+        self.mop(MopId::ROOT).traces()
     }
 
-    pub fn epitomes(&self) -> OrderedSet<Rc<Mop<T, S>>> {
-        self.mop.epitomes()
+    pub fn epitomes(&self) -> OrderedSet<MopRef<'_, T, S>> {
+        self.mop(MopId::ROOT).epitomes()
+    }
+
+    /// Describe the tree in Graphviz DOT.  Each mop appears once labelled
+    /// with its elements and its trace, epitome and undif strengths.  Real
+    /// links are drawn solid and virtual links dashed and each edge is
+    /// labelled with the child indices that lead along it.
+    pub fn to_dot(&self) -> String {
+        let mut dot = "digraph rdt {\n\tnode [shape=box];\n".to_string();
+        for (id, record) in self.to_records().iter().enumerate() {
+            let elements: OrderedSet<T> = record.elements.iter().collect();
+            dot.push_str(&format!(
+                "\tn{id} [label=\"{}\\nT: {:.4} E: {:.4} U: {:.4}\"];\n",
+                escape_dot(&format_set(&elements)),
+                record.trace_strength.value(),
+                record.epitome_strength.value(),
+                record.undif_strength.value()
+            ));
+            for (style, links) in [
+                ("solid", &record.children_r),
+                ("dashed", &record.children_v),
+            ] {
+                let mut edges = OrderedMap::<u32, Vec<&T>>::new();
+                for (j, child) in links.iter() {
+                    match edges.get_mut(child) {
+                        Some(indices) => indices.push(j),
+                        None => {
+                            edges.insert(*child, vec![j]);
+                        }
+                    }
+                }
+                for (child, indices) in edges.iter() {
+                    dot.push_str(&format!(
+                        "\tn{id} -> n{child} [style={style}, label=\"{}\"];\n",
+                        escape_dot(&format!("{indices:?}"))
+                    ));
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
     }
 }
 
@@ -683,81 +1203,75 @@ fn format_set<T: Ord + Debug + Clone>(set: &OrderedSet<T>) -> String {
     format!("{v:?}")
 }
 
-impl<T: Ord + Debug + Clone, S: Strength> Mop<T, S> {
-    fn format_mop_short(&self) -> String {
-        let big_c: Vec<&T> = self.elements.iter().collect();
-        let childen_r = self.children_r.borrow();
-        let big_i_r: Vec<&T> = childen_r.keys().collect();
-        let childen_v = self.children_v.borrow();
-        let big_i_v: Vec<&T> = childen_v.keys().collect();
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl<T: Ord + Debug + Clone, S: Strength> RedundantDiscriminationTree<T, S> {
+    fn format_mop_short(&self, id: MopId) -> String {
+        let mop = self.mops.get(id);
+        let big_c: Vec<&T> = mop.elements.iter().collect();
+        let big_i_r: Vec<&T> = mop.children_r.keys().collect();
+        let big_i_v: Vec<&T> = mop.children_v.keys().collect();
         format!("C: {big_c:?} I_r: {big_i_r:?} I_v: {big_i_v:?}")
     }
 
-    fn format_mop(&self) -> String {
-        if self.children_r.borrow().len() == 0 && self.children_v.borrow().len() == 0 {
-            return format!("C: {} {{}}", format_set(&self.elements));
+    fn format_mop(&self, id: MopId) -> String {
+        let mop = self.mops.get(id);
+        if !mop.is_epitome() {
+            return format!("C: {} {{}}", format_set(&mop.elements));
         }
-        let mut fstr = format!("C: {} {{\n", format_set(&self.elements));
-        let mut big_a = self.children_r.borrow().keys().to_set();
-        while let Some(j) = big_a.first() {
-            let (j_mop, j_mop_indices) = self.get_r_child_and_indices(j).unwrap();
-            let tstr = format!(
-                "\tR: {} -> {}\n",
-                format_set(&j_mop_indices),
-                j_mop.format_mop_short()
-            );
-            fstr.push_str(&tstr);
-            big_a -= j_mop_indices;
-        }
-        let mut big_a = self.children_v.borrow().keys().to_set();
-        while let Some(j) = big_a.first() {
-            let (j_mop, j_mop_indices) = self.get_v_child_and_indices(j).unwrap();
-            let tstr = format!(
-                "\tV: {} -> {}\n",
-                format_set(&j_mop_indices),
-                j_mop.format_mop_short()
-            );
-            fstr.push_str(&tstr);
-            big_a -= j_mop_indices;
+        let mut fstr = format!("C: {} {{\n", format_set(&mop.elements));
+        for (tag, real) in [("R", true), ("V", false)] {
+            for (j, j_mop) in mop.links(real).iter() {
+                if self.first_link_index(id, real, *j_mop, None) == Some(j) {
+                    let indices: Vec<&T> = self.link_indices(id, real, *j_mop).collect();
+                    let tstr = format!(
+                        "\t{tag}: {indices:?} -> {}\n",
+                        self.format_mop_short(*j_mop)
+                    );
+                    fstr.push_str(&tstr);
+                }
+            }
         }
         fstr.push('}');
         fstr
     }
 
-    fn verify_mop(&self) -> bool {
+    fn verify_mop(&self, id: MopId) -> bool {
+        let mop = self.mops.get(id);
         let mut result = true;
-        let r_indices = self.children_r.borrow().keys().to_set();
-        let v_indices = self.children_v.borrow().keys().to_set();
-        if !r_indices.is_disjoint(&self.elements) {
+        let r_indices = mop.children_r.keys().to_set();
+        let v_indices = mop.children_v.keys().to_set();
+        if !r_indices.is_disjoint(&mop.elements) {
             println!(
                 "real indices overlap C {} <> {}",
                 format_set(&r_indices),
-                format_set(&self.elements)
+                format_set(&mop.elements)
             );
             result = false;
         };
-        if !v_indices.is_disjoint(&self.elements) {
+        if !v_indices.is_disjoint(&mop.elements) {
             println!(
                 "virt indices overlap C {} <> {}",
                 format_set(&v_indices),
-                format_set(&self.elements)
+                format_set(&mop.elements)
             );
             result = false;
         };
         if !r_indices.is_disjoint(&v_indices) {
-            println!("child indices overlap {}", self.format_mop());
+            println!("child indices overlap {}", self.format_mop(id));
             result = false;
         };
         result
     }
 
-    fn verify_tree(&self) -> bool {
-        let mut result = self.verify_mop();
-        let mut big_j = self.children_r.borrow().keys().to_set();
-        while let Some(j) = big_j.first() {
-            let (j_mop, j_mop_indices) = self.get_r_child_and_indices(j).unwrap();
-            big_j = big_j.difference(&j_mop_indices).to_set();
-            result = result && j_mop.verify_tree();
+    fn verify_tree(&self, id: MopId) -> bool {
+        let mut result = self.verify_mop(id);
+        for (j, j_mop) in self.mops.get(id).children_r.iter() {
+            if self.first_link_index(id, true, *j_mop, None) == Some(j) {
+                result = result && self.verify_tree(*j_mop);
+            }
         }
         result
     }
@@ -841,4 +1355,558 @@ mod tests {
         assert_eq!(rdt.epitomes().len(), 9);
         rdt.decrement_strengths();
     }
+
+    pub(crate) fn pseudo_random_excerpts(count: usize, seed: u64) -> Vec<OrderedSet<u8>> {
+        let mut state = seed;
+        let mut excerpts = vec![];
+        for _ in 0..count {
+            let mut elements = vec![];
+            for element in 0..8u8 {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                if (state >> 33).is_multiple_of(3) {
+                    elements.push(element);
+                }
+            }
+            if elements.is_empty() {
+                elements.push((state >> 40) as u8 % 8);
+            }
+            excerpts.push(elements.into());
+        }
+        excerpts
+    }
+
+    fn element_sets<T: Ord + Debug + Clone, S: Strength>(
+        mops: &OrderedSet<MopRef<'_, T, S>>,
+    ) -> Vec<OrderedSet<T>> {
+        mops.iter().map(|mop| mop.elements().clone()).collect()
+    }
+
+    #[test]
+    fn weighted_inclusion() {
+        let excerpts = pseudo_random_excerpts(30, 23);
+        let mut repeated = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        let mut weighted = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for (i, excerpt) in excerpts.iter().enumerate() {
+            let times = i % 3 + 1;
+            for _ in 0..times {
+                repeated.include_excerpt(excerpt.clone());
+            }
+            weighted.include_weighted_excerpt(excerpt.clone(), times as f64);
+        }
+        assert_eq!(
+            element_sets(&weighted.traces()),
+            element_sets(&repeated.traces())
+        );
+        assert_eq!(
+            element_sets(&weighted.epitomes()),
+            element_sets(&repeated.epitomes())
+        );
+        for (a, b) in weighted.all_mops().iter().zip(repeated.all_mops().iter()) {
+            assert!((a.trace_strength() - b.trace_strength()).abs() < 1e-12);
+            assert!((a.epitome_strength() - b.epitome_strength()).abs() < 1e-12);
+        }
+
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        rdt.include_weighted_excerpt(excerpts[0].clone(), 0.5);
+        let light = rdt.complete_match(&excerpts[0]).unwrap().trace_strength();
+        assert!(light > 0.0 && light < SimpleStrength::new(true).value());
+    }
+
+    // A strength that relies on the default (rounding) `increase_by()`.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+    struct StepStrength(u32);
+
+    impl Strength for StepStrength {
+        const DECAY_RATE: f64 = 1.0;
+
+        fn new(incr_value: bool) -> Self {
+            Self(u32::from(incr_value))
+        }
+
+        fn value(&self) -> f64 {
+            f64::from(self.0)
+        }
+
+        fn increase(&mut self) {
+            self.0 += 1;
+        }
+
+        fn decrease(&mut self) {}
+    }
+
+    #[test]
+    fn fractional_weights_of_rounding_strengths() {
+        let mut rdt = RedundantDiscriminationTree::<u8, StepStrength>::new();
+        rdt.include_weighted_excerpt(vec![1, 2].into(), 1.6);
+        let trace = rdt.complete_match(&vec![1, 2].into()).unwrap();
+        assert_eq!(trace.trace_strength(), 2.0);
+        let records = rdt.to_records();
+        for weight in [0.4, f64::INFINITY, f64::NAN, 0.0] {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                rdt.include_weighted_excerpt(vec![1, 3].into(), weight)
+            }));
+            assert!(result.is_err());
+            assert_eq!(rdt.to_records(), records);
+        }
+        assert_eq!(rdt.traces().len(), 1);
+    }
+
+    #[test]
+    fn count_weights_are_validated() {
+        let mut rdt = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        rdt.include_weighted_excerpt(vec![1, 2].into(), 2.6);
+        assert_eq!(
+            rdt.complete_match(&vec![1, 2].into())
+                .unwrap()
+                .trace_count(),
+            3
+        );
+        let records = rdt.to_records();
+        for weight in [0.4, f64::INFINITY, 1e30] {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                rdt.include_weighted_excerpt(vec![1, 3].into(), weight)
+            }));
+            assert!(result.is_err());
+            assert_eq!(rdt.to_records(), records);
+        }
+        let mut rdt = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        rdt.include_weighted_excerpt(vec![1, 2].into(), 9e18);
+        rdt.include_weighted_excerpt(vec![2, 3].into(), 9e18);
+        let records = rdt.to_records();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            rdt.include_weighted_excerpt(vec![1, 2].into(), 5e17)
+        }));
+        assert!(result.is_err());
+        assert_eq!(rdt.to_records(), records);
+        let mut count = CountStrength::new(true);
+        let result = std::panic::catch_unwind(move || count.increase_by(0.4));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn timed_strengths_decay_lazily() {
+        let mut timed = RedundantDiscriminationTree::<u8, TimedStrength>::new();
+        let mut simple = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for (i, excerpt) in pseudo_random_excerpts(40, 29).into_iter().enumerate() {
+            timed.include_excerpt(excerpt.clone());
+            simple.include_excerpt(excerpt);
+            for _ in 0..i % 4 {
+                timed.decrement_strengths();
+                simple.decrement_strengths();
+            }
+        }
+        let timed_mops = timed.all_mops();
+        let simple_mops = simple.all_mops();
+        assert_eq!(timed_mops.len(), simple_mops.len());
+        for (a, b) in timed_mops.iter().zip(simple_mops.iter()) {
+            assert_eq!(a.elements(), b.elements());
+            assert!((a.trace_strength() - b.trace_strength()).abs() < 1e-12);
+            assert!((a.epitome_strength() - b.epitome_strength()).abs() < 1e-12);
+            assert!((a.undif_strength() - b.undif_strength()).abs() < 1e-12);
+        }
+
+        timed.prune(0.5);
+        simple.prune(0.5);
+        assert_eq!(
+            element_sets(&timed.traces()),
+            element_sets(&simple.traces())
+        );
+    }
+
+    #[test]
+    fn timed_trees_age_independently() {
+        let excerpts = pseudo_random_excerpts(30, 41);
+        let mut young = RedundantDiscriminationTree::<u8, TimedStrength>::new();
+        let mut old = RedundantDiscriminationTree::<u8, TimedStrength>::new();
+        for excerpt in excerpts.iter() {
+            young.include_excerpt(excerpt.clone());
+            old.include_excerpt(excerpt.clone());
+        }
+        let records = young.to_records();
+        for _ in 0..10 {
+            old.decrement_strengths();
+        }
+        assert_eq!(young.to_records(), records);
+        for (a, b) in young.all_mops().iter().zip(old.all_mops().iter()) {
+            assert_eq!(a.elements(), b.elements());
+            let decayed = a.undif_strength() * TimedStrength::DECAY_RATE.powi(10);
+            assert!((b.undif_strength() - decayed).abs() < 1e-12);
+        }
+
+        young.include_excerpt(excerpts[0].clone());
+        old.include_excerpt(excerpts[0].clone());
+        let trace = young.complete_match(&excerpts[0]).unwrap();
+        assert!(
+            trace.trace_strength() > old.complete_match(&excerpts[0]).unwrap().trace_strength()
+        );
+    }
+
+    #[test]
+    fn count_strengths_are_support_counts() {
+        let excerpts = pseudo_random_excerpts(50, 31);
+        let mut rdt = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        for excerpt in excerpts.iter() {
+            rdt.include_excerpt(excerpt.clone());
+        }
+        rdt.include_weighted_excerpt(excerpts[0].clone(), 2.0);
+        rdt.decrement_strengths();
+        let mut all = excerpts.clone();
+        all.push(excerpts[0].clone());
+        all.push(excerpts[0].clone());
+        for mop in rdt.all_mops().iter() {
+            let count = |wanted: &dyn Fn(&OrderedSet<u8>) -> bool| {
+                all.iter().filter(|excerpt| wanted(excerpt)).count() as u64
+            };
+            assert_eq!(mop.trace_count(), count(&|x| x == mop.elements()));
+            assert_eq!(mop.undif_count(), count(&|x| x.is_superset(mop.elements())));
+            assert_eq!(
+                mop.epitome_count(),
+                count(&|x| x.is_superset(mop.elements()) && x != mop.elements())
+            );
+            assert_eq!(mop.trace_strength(), mop.trace_count() as f64);
+        }
+    }
+
+    #[test]
+    fn configured_rates() {
+        let excerpts = pseudo_random_excerpts(20, 37);
+        let mut default = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        let defaults = StrengthConfig::of::<SimpleStrength>();
+        let mut same = RedundantDiscriminationTree::<u8, SimpleStrength>::with_config(defaults);
+        let config = StrengthConfig {
+            decay_rate: 0.5,
+            growth_rate: 0.25,
+        };
+        let mut fast = RedundantDiscriminationTree::<u8, SimpleStrength>::with_config(config);
+        for excerpt in excerpts.iter() {
+            default.include_excerpt(excerpt.clone());
+            same.include_excerpt(excerpt.clone());
+            fast.include_excerpt(excerpt.clone());
+        }
+        for (a, b) in default.all_mops().iter().zip(same.all_mops().iter()) {
+            assert_eq!(a.trace_strength(), b.trace_strength());
+            assert_eq!(a.undif_strength(), b.undif_strength());
+        }
+        let mut single = RedundantDiscriminationTree::<u8, SimpleStrength>::with_config(config);
+        single.include_excerpt(excerpts[0].clone());
+        let trace = single.complete_match(&excerpts[0]).unwrap();
+        assert_eq!(trace.trace_strength(), 0.25);
+        single.decrement_strengths();
+        let trace = single.complete_match(&excerpts[0]).unwrap();
+        assert_eq!(trace.trace_strength(), 0.125);
+        assert_eq!(
+            element_sets(&fast.traces()),
+            element_sets(&default.traces())
+        );
+    }
+
+    #[test]
+    fn timed_strengths_use_configured_rates() {
+        let config = StrengthConfig {
+            decay_rate: 0.5,
+            growth_rate: 0.25,
+        };
+        let mut timed = RedundantDiscriminationTree::<u8, TimedStrength>::with_config(config);
+        let mut simple = RedundantDiscriminationTree::<u8, SimpleStrength>::with_config(config);
+        for (i, excerpt) in pseudo_random_excerpts(30, 43).into_iter().enumerate() {
+            timed.include_weighted_excerpt(excerpt.clone(), 1.5);
+            simple.include_weighted_excerpt(excerpt, 1.5);
+            for _ in 0..i % 3 {
+                timed.decrement_strengths();
+                simple.decrement_strengths();
+            }
+        }
+        for (a, b) in timed.all_mops().iter().zip(simple.all_mops().iter()) {
+            assert_eq!(a.elements(), b.elements());
+            assert!((a.trace_strength() - b.trace_strength()).abs() < 1e-12);
+            assert!((a.undif_strength() - b.undif_strength()).abs() < 1e-12);
+        }
+        let mut single = RedundantDiscriminationTree::<u8, TimedStrength>::with_config(config);
+        single.include_excerpt(vec![1, 2].into());
+        single.decrement_strengths();
+        let trace = single.complete_match(&vec![1, 2].into()).unwrap();
+        assert_eq!(trace.trace_strength(), 0.125);
+    }
+
+    #[test]
+    #[should_panic(expected = "growth must be above 0.0")]
+    fn zero_growth_rate_is_rejected() {
+        RedundantDiscriminationTree::<u8, TimedStrength>::with_config(StrengthConfig {
+            decay_rate: 0.5,
+            growth_rate: 0.0,
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "only supports its own rates")]
+    fn counts_reject_configured_rates() {
+        RedundantDiscriminationTree::<u8, CountStrength>::with_config(StrengthConfig {
+            decay_rate: 0.5,
+            growth_rate: 0.5,
+        });
+    }
+
+    #[test]
+    fn label_strengths_count_labelled_excerpts() {
+        let excerpts = pseudo_random_excerpts(60, 101);
+        let label = |excerpt: &OrderedSet<u8>| match excerpt.first() {
+            Some(0) => Some("zero"),
+            Some(1) => Some("one"),
+            _ => None,
+        };
+        let mut rdt = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        for excerpt in excerpts.iter() {
+            match label(excerpt) {
+                Some(label) => rdt.include_labelled_excerpt(excerpt.clone(), label),
+                None => rdt.include_excerpt(excerpt.clone()),
+            }
+        }
+        for mop in rdt.all_mops().iter() {
+            for name in ["zero", "one"] {
+                let count = excerpts
+                    .iter()
+                    .filter(|x| label(x) == Some(name) && x.is_superset(mop.elements()))
+                    .count();
+                assert_eq!(mop.label_strength(name), count as f64);
+                assert_eq!(mop.labels().contains(&name.to_string()), count > 0);
+            }
+        }
+        assert_eq!(
+            rdt.mop(MopId::ROOT).labels(),
+            vec!["one".to_string(), "zero".to_string()]
+        );
+    }
+
+    #[test]
+    fn batch_inclusion_matches_sequential_inclusion() {
+        let excerpts = pseudo_random_excerpts(200, 113);
+        let mut sequential = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        for excerpt in excerpts.iter() {
+            sequential.include_excerpt(excerpt.clone());
+        }
+        let mut batched = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        batched.include_excerpts(excerpts.iter().cloned());
+        let strengths = |rdt: &RedundantDiscriminationTree<u8, CountStrength>| {
+            rdt.all_mops()
+                .iter()
+                .map(|mop| {
+                    (
+                        mop.elements().clone(),
+                        mop.trace_count(),
+                        mop.epitome_count(),
+                        mop.undif_count(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(strengths(&batched), strengths(&sequential));
+        assert_eq!(
+            element_sets(&batched.traces()),
+            element_sets(&sequential.traces())
+        );
+        assert_eq!(
+            element_sets(&batched.epitomes()),
+            element_sets(&sequential.epitomes())
+        );
+
+        let mut sequential = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        let mut batched = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for excerpt in excerpts[..100].iter() {
+            sequential.include_excerpt(excerpt.clone());
+        }
+        batched.include_excerpts(excerpts[..100].iter().cloned());
+        sequential.decrement_strengths();
+        batched.decrement_strengths();
+        for excerpt in excerpts[100..].iter() {
+            sequential.include_excerpt(excerpt.clone());
+        }
+        batched.include_excerpts(excerpts[100..].to_vec());
+        let traces = sequential.traces();
+        assert_eq!(element_sets(&batched.traces()), element_sets(&traces));
+        for trace in traces.iter() {
+            let found = batched.complete_match(trace.elements()).unwrap();
+            assert_eq!(found.elements(), trace.elements());
+            assert!((found.trace_strength() - trace.trace_strength()).abs() < 1e-12);
+            assert!((found.undif_strength() - trace.undif_strength()).abs() < 1e-12);
+        }
+        assert_eq!(
+            element_sets(&batched.epitomes()),
+            element_sets(&sequential.epitomes())
+        );
+    }
+
+    #[test]
+    fn to_dot_draws_each_mop_once() {
+        let mut rdt = RedundantDiscriminationTree::<&str, SimpleStrength>::new();
+        rdt.include_experience(&["a", "b", "c", "d"]);
+        rdt.include_experience(&["a", "b", "c"]);
+        rdt.include_experience(&["a", "b", "d"]);
+        rdt.include_experience(&["a", "\"d\""]);
+        let dot = rdt.to_dot();
+        assert!(dot.starts_with("digraph rdt {\n") && dot.ends_with("}\n"));
+        let nodes = dot.lines().filter(|line| line.contains("[label=")).count();
+        assert_eq!(nodes, rdt.all_mops().len());
+        let edges = |style: &str| -> usize {
+            let style = format!("style={style}");
+            dot.lines().filter(|line| line.contains(&style)).count()
+        };
+        let distinct = |children: &OrderedMap<&str, MopId>| {
+            children
+                .iter()
+                .map(|(_, child)| child)
+                .collect::<OrderedSet<MopId>>()
+                .len()
+        };
+        let mut expected_r = 0;
+        let mut expected_v = 0;
+        for mop in rdt.all_mops().iter() {
+            expected_r += distinct(&rdt.mops.get(mop.id()).children_r);
+            expected_v += distinct(&rdt.mops.get(mop.id()).children_v);
+        }
+        assert_eq!(edges("solid"), expected_r);
+        assert_eq!(edges("dashed"), expected_v);
+        assert!(expected_v > 0);
+        assert!(dot.contains(r#"[\"\\\"d\\\"\""#));
+    }
+
+    #[test]
+    fn prune_forgets_weak_traces() {
+        let excerpts = pseudo_random_excerpts(40, 7);
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for excerpt in excerpts.iter() {
+            rdt.include_excerpt(excerpt.clone());
+        }
+        let traces = element_sets(&rdt.traces());
+        let epitomes = element_sets(&rdt.epitomes());
+        rdt.prune(0.0);
+        assert_eq!(element_sets(&rdt.traces()), traces);
+        assert_eq!(element_sets(&rdt.epitomes()), epitomes);
+
+        for _ in 0..60 {
+            rdt.decrement_strengths();
+        }
+        for excerpt in excerpts.iter().step_by(3) {
+            rdt.include_excerpt(excerpt.clone());
+        }
+        let survivors: Vec<OrderedSet<u8>> = rdt
+            .traces()
+            .iter()
+            .filter(|mop| {
+                !rdt.mops
+                    .get(mop.id())
+                    .is_below(0.04, rdt.clock, &rdt.config)
+            })
+            .map(|mop| mop.elements().clone())
+            .collect();
+        assert!(survivors.len() < rdt.traces().len());
+        rdt.prune(0.04);
+        let mut expected = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for excerpt in survivors.iter() {
+            expected.include_excerpt(excerpt.clone());
+        }
+        assert_eq!(element_sets(&rdt.traces()), survivors);
+        assert_eq!(
+            element_sets(&rdt.epitomes()),
+            element_sets(&expected.epitomes())
+        );
+        for excerpt in survivors.iter() {
+            assert_eq!(rdt.complete_match(excerpt).unwrap().elements(), excerpt);
+        }
+    }
+
+    #[test]
+    fn exclude_excerpt_undoes_inclusion() {
+        let mut rdt = RedundantDiscriminationTree::<&str, SimpleStrength>::new();
+        for experience in [
+            &["a", "b", "c", "d"][..],
+            &["a", "b", "c"],
+            &["a", "b", "d"],
+            &["a", "d"],
+        ] {
+            rdt.include_experience(experience);
+        }
+        let traces = element_sets(&rdt.traces());
+        let epitomes = element_sets(&rdt.epitomes());
+        let excerpt: OrderedSet<&str> = vec!["e", "b", "d"].into();
+        rdt.include_excerpt(excerpt.clone());
+        assert!(rdt.exclude_excerpt(&excerpt));
+        assert!(!rdt.exclude_excerpt(&excerpt));
+        assert!(!rdt.exclude_excerpt(&vec!["a", "b"].into()));
+        assert_eq!(element_sets(&rdt.traces()), traces);
+        assert_eq!(element_sets(&rdt.epitomes()), epitomes);
+
+        let excerpts = pseudo_random_excerpts(40, 11);
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for excerpt in excerpts.iter() {
+            rdt.include_excerpt(excerpt.clone());
+        }
+        let excluded: Vec<&OrderedSet<u8>> = excerpts.iter().step_by(3).collect();
+        for excerpt in excluded.iter() {
+            rdt.exclude_excerpt(excerpt);
+        }
+        let mut expected = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for excerpt in excerpts.iter() {
+            if !excluded.contains(&excerpt) {
+                expected.include_excerpt(excerpt.clone());
+            }
+        }
+        assert_eq!(
+            element_sets(&rdt.traces()),
+            element_sets(&expected.traces())
+        );
+        assert_eq!(
+            element_sets(&rdt.epitomes()),
+            element_sets(&expected.epitomes())
+        );
+    }
+
+    #[test]
+    fn exclude_excerpt_retracts_strengths() {
+        let excerpts = pseudo_random_excerpts(50, 59);
+        let labels = ["x", "y", "z"];
+        let include = |rdt: &mut RedundantDiscriminationTree<u8, CountStrength>, i: usize| {
+            if i % 4 == 3 {
+                rdt.include_excerpt(excerpts[i].clone());
+            } else {
+                rdt.include_labelled_excerpt(excerpts[i].clone(), labels[i % 4]);
+            }
+        };
+        let mut rdt = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        for i in 0..excerpts.len() {
+            include(&mut rdt, i);
+            if i % 5 == 0 {
+                include(&mut rdt, i);
+            }
+        }
+        let excluded: Vec<&OrderedSet<u8>> = excerpts.iter().step_by(3).collect();
+        for excerpt in excluded.iter() {
+            rdt.exclude_excerpt(excerpt);
+        }
+        let mut expected = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        for (i, excerpt) in excerpts.iter().enumerate() {
+            if !excluded.contains(&excerpt) {
+                include(&mut expected, i);
+                if i % 5 == 0 {
+                    include(&mut expected, i);
+                }
+            }
+        }
+        let (mops, expected_mops) = (rdt.all_mops(), expected.all_mops());
+        assert_eq!(mops.len(), expected_mops.len());
+        for (mop, expected_mop) in mops.iter().zip(expected_mops.iter()) {
+            assert_eq!(mop.elements(), expected_mop.elements());
+            assert_eq!(mop.trace_count(), expected_mop.trace_count());
+            assert_eq!(mop.epitome_count(), expected_mop.epitome_count());
+            assert_eq!(mop.undif_count(), expected_mop.undif_count());
+            assert_eq!(mop.labels(), expected_mop.labels());
+            for label in labels {
+                assert_eq!(
+                    mop.label_strength(label),
+                    expected_mop.label_strength(label)
+                );
+            }
+        }
+    }
 }
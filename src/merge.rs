@@ -0,0 +1,277 @@
+// Copyright 2021 Peter Williams <pwil3058@gmail.com> <pwil3058@bigpond.net.au>
+
+//! Merging trees built from different excerpts (e.g. from separate shards
+//! or time windows) into one.
+//!
+//! The merged tree has a trace for each trace of either tree and an
+//! epitome for each intersection of them.  Its strengths are combined
+//! from what each tree's strengths would be for the same elements: a
+//! tree's undif strength for any element set is that of the set's complete
+//! match (which has the strength of all the excerpts containing the set)
+//! and its epitome strength for a set that is not one of its mops is the
+//! same as its undif strength as all the excerpts containing the set
+//! contain more than it.
+
+use std::fmt::Debug;
+
+use ordered_collections::{OrderedMap, OrderedSet};
+
+use crate::arena::MopId;
+use crate::strength::Strength;
+use crate::RedundantDiscriminationTree;
+
+// What `rdt`'s trace, epitome, undif and label strengths are for `elements`
+// rebased to the time `now`
+fn strengths_for<T: Ord + Debug + Clone, S: Strength>(
+    rdt: &RedundantDiscriminationTree<T, S>,
+    elements: &OrderedSet<T>,
+    now: u64,
+) -> (S, S, S, OrderedMap<String, S>) {
+    let rebased = |strength: &S| strength.rebased(rdt.clock, now, &rdt.config);
+    let labels = |label_strengths: &OrderedMap<String, S>| {
+        let mut labels = OrderedMap::<String, S>::new();
+        for (label, strength) in label_strengths.iter() {
+            labels.insert(label.clone(), rebased(strength));
+        }
+        labels
+    };
+    match rdt
+        .complete_match(elements)
+        .map(|mop| rdt.mops.get(mop.id()))
+    {
+        Some(mop) if mop.elements == *elements => (
+            rebased(&mop.trace_strength),
+            rebased(&mop.epitome_strength),
+            rebased(&mop.undif_strength),
+            labels(&mop.label_strengths),
+        ),
+        Some(mop) => (
+            S::new(false),
+            rebased(&mop.undif_strength),
+            rebased(&mop.undif_strength),
+            labels(&mop.label_strengths),
+        ),
+        None => (
+            S::new(false),
+            S::new(false),
+            S::new(false),
+            OrderedMap::new(),
+        ),
+    }
+}
+
+impl<T: Ord + Debug + Clone, S: Strength> RedundantDiscriminationTree<T, S> {
+    /// Merge `other` into this tree combining their strengths with
+    /// `Strength::combine()`.  For `CountStrength` (and, apart from any
+    /// decay, `SimpleStrength`) the result is the same as if this tree had
+    /// also included the excerpts that `other` was built from.
+    pub fn merge(&mut self, other: &Self) {
+        self.merge_with(other, S::combine);
+    }
+
+    /// Merge `other` into this tree using `combine` to decide each mop's
+    /// strengths from what they are in this tree and in `other`.  Any trace
+    /// whose combined trace strength is zero is forgotten and mops that are
+    /// then no longer needed are discarded.  This tree's config and clock
+    /// are kept and `other`'s strengths are rebased to this tree's clock.
+    pub fn merge_with<F: Fn(&S, &S) -> S>(&mut self, other: &Self, combine: F) {
+        let mut excerpts = OrderedSet::<OrderedSet<T>>::new();
+        for trace in self.iter_traces().chain(other.iter_traces()) {
+            excerpts.insert(trace.elements().clone());
+        }
+        let mut merged = Self::with_config(self.config);
+        merged.clock = self.clock;
+        for excerpt in excerpts.iter() {
+            merged.reorganize_and_absorb(excerpt, 1.0, None);
+        }
+        let ids: Vec<MopId> = merged.mops.ids().collect();
+        for id in ids {
+            let elements = merged.mops.get(id).elements.clone();
+            let (a_trace, a_epitome, a_undif, a_labels) =
+                strengths_for(self, &elements, merged.clock);
+            let (b_trace, b_epitome, b_undif, b_labels) =
+                strengths_for(other, &elements, merged.clock);
+            let mop = merged.mops.get_mut(id);
+            mop.trace_strength = combine(&a_trace, &b_trace);
+            mop.epitome_strength = combine(&a_epitome, &b_epitome);
+            mop.undif_strength = combine(&a_undif, &b_undif);
+            let mut labels = OrderedMap::<String, S>::new();
+            for (label, a_strength) in a_labels.iter() {
+                let b_strength = b_labels
+                    .get(label)
+                    .copied()
+                    .unwrap_or_else(|| S::new(false));
+                labels.insert(label.clone(), combine(a_strength, &b_strength));
+            }
+            for (label, b_strength) in b_labels.iter() {
+                if a_labels.get(label).is_none() {
+                    labels.insert(label.clone(), combine(&S::new(false), b_strength));
+                }
+            }
+            mop.label_strengths = labels;
+        }
+        merged.tidy();
+        assert!(merged.verify_tree(MopId::ROOT));
+        *self = merged;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strength::{CountStrength, SimpleStrength};
+    use crate::tests::pseudo_random_excerpts;
+    use crate::MopRef;
+
+    // Each mop's elements, trace, epitome and undif counts and labels
+    type Counts = Vec<(OrderedSet<u8>, u64, u64, u64, Vec<(String, f64)>)>;
+
+    fn counts(rdt: &RedundantDiscriminationTree<u8, CountStrength>) -> Counts {
+        rdt.all_mops()
+            .iter()
+            .map(|mop| {
+                let labels = mop
+                    .labels()
+                    .into_iter()
+                    .map(|label| {
+                        let strength = mop.label_strength(&label);
+                        (label, strength)
+                    })
+                    .collect();
+                (
+                    mop.elements().clone(),
+                    mop.trace_count(),
+                    mop.epitome_count(),
+                    mop.undif_count(),
+                    labels,
+                )
+            })
+            .collect()
+    }
+
+    fn element_sets<'a, S: Strength + 'a>(
+        mops: impl Iterator<Item = MopRef<'a, u8, S>>,
+    ) -> OrderedSet<OrderedSet<u8>> {
+        mops.map(|mop| mop.elements().clone())
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    #[test]
+    fn merged_counts_match_including_everything() {
+        let excerpts = pseudo_random_excerpts(120, 127);
+        let mut everything = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        let mut first = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        let mut second = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        for (i, excerpt) in excerpts.iter().enumerate() {
+            let (rdt, label) = if i < 70 {
+                (&mut first, "first")
+            } else {
+                (&mut second, "second")
+            };
+            if i.is_multiple_of(4) {
+                everything.include_labelled_excerpt(excerpt.clone(), label);
+                rdt.include_labelled_excerpt(excerpt.clone(), label);
+            } else {
+                everything.include_excerpt(excerpt.clone());
+                rdt.include_excerpt(excerpt.clone());
+            }
+        }
+        first.merge(&second);
+        assert_eq!(counts(&first), counts(&everything));
+        assert_eq!(
+            element_sets(first.iter_epitomes()),
+            element_sets(everything.iter_epitomes())
+        );
+
+        let mut empty = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        empty.merge(&everything);
+        assert_eq!(counts(&empty), counts(&everything));
+        everything.merge(&RedundantDiscriminationTree::new());
+        assert_eq!(counts(&empty), counts(&everything));
+
+        let mut big = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        big.include_weighted_excerpt(vec![1, 2].into(), 1e19);
+        let (other, records) = (big.clone(), big.to_records());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| big.merge(&other)));
+        assert!(result.is_err());
+        assert_eq!(big.to_records(), records);
+    }
+
+    #[test]
+    fn merged_strengths_combine_increases() {
+        let excerpts = pseudo_random_excerpts(80, 131);
+        let mut everything = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        let mut first = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        let mut second = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for (i, excerpt) in excerpts.into_iter().enumerate() {
+            everything.include_excerpt(excerpt.clone());
+            if i.is_multiple_of(3) {
+                first.include_excerpt(excerpt);
+            } else {
+                second.include_excerpt(excerpt);
+            }
+        }
+        first.merge(&second);
+        let all_mops = everything.all_mops();
+        assert_eq!(
+            element_sets(first.all_mops().iter().cloned()),
+            element_sets(all_mops.iter().cloned())
+        );
+        for mop in all_mops.iter() {
+            let found = first.complete_match(mop.elements()).unwrap();
+            assert!((found.trace_strength() - mop.trace_strength()).abs() < 1e-12);
+            assert!((found.epitome_strength() - mop.epitome_strength()).abs() < 1e-12);
+            assert!((found.undif_strength() - mop.undif_strength()).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn merge_with_custom_rule() {
+        let mut first = RedundantDiscriminationTree::<&str, CountStrength>::new();
+        first.include_experience(&["a", "b"]);
+        first.include_experience(&["a", "b"]);
+        first.include_experience(&["a", "c"]);
+        let mut second = RedundantDiscriminationTree::<&str, CountStrength>::new();
+        second.include_experience(&["a", "b"]);
+        second.include_experience(&["b", "d"]);
+
+        let mut strongest = RedundantDiscriminationTree::<&str, CountStrength>::new();
+        strongest.merge(&first);
+        strongest.merge_with(&second, |a, b| if a >= b { *a } else { *b });
+        let traces: Vec<(Vec<&str>, u64)> = strongest
+            .traces()
+            .iter()
+            .map(|mop| (mop.elements().iter().cloned().collect(), mop.trace_count()))
+            .collect();
+        assert_eq!(
+            traces,
+            vec![
+                (vec!["a", "b"], 2),
+                (vec!["a", "c"], 1),
+                (vec!["b", "d"], 1)
+            ]
+        );
+        assert_eq!(strongest.mop(MopId::ROOT).undif_count(), 3);
+
+        // keeping only this tree's strengths discards the other's traces
+        // each mop's elements (concatenated) and its trace and undif counts
+        fn counts(
+            rdt: &RedundantDiscriminationTree<&str, CountStrength>,
+        ) -> Vec<(String, u64, u64)> {
+            rdt.all_mops()
+                .iter()
+                .map(|mop| {
+                    (
+                        mop.elements().iter().cloned().collect(),
+                        mop.trace_count(),
+                        mop.undif_count(),
+                    )
+                })
+                .collect()
+        }
+        let before = counts(&first);
+        first.merge_with(&second, |a, _| *a);
+        assert_eq!(counts(&first), before);
+    }
+}
@@ -0,0 +1,208 @@
+// Copyright 2021 Peter Williams <pwil3058@gmail.com> <pwil3058@bigpond.net.au>
+
+//! Frequent pattern mining from the epitomes of a tree.
+//!
+//! A mop's undif strength is the strength of all the excerpts that contain
+//! its elements so, relative to the root's undif strength, it is the
+//! support of those elements.  With `CountStrength` the supports are exact
+//! frequencies and with decaying strengths they favour recent excerpts.
+
+use std::fmt::Debug;
+
+use ordered_collections::OrderedSet;
+
+use crate::arena::MopId;
+use crate::strength::Strength;
+use crate::RedundantDiscriminationTree;
+
+/// The rule that excerpts containing `antecedent` also contain `consequent`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssociationRule<T: Ord + Debug + Clone> {
+    pub antecedent: OrderedSet<T>,
+    pub consequent: OrderedSet<T>,
+    /// The support of `antecedent` ∪ `consequent`.
+    pub support: f64,
+    /// The support of `antecedent` ∪ `consequent` relative to that of
+    /// `antecedent`.
+    pub confidence: f64,
+    /// `confidence` relative to the support of `consequent`.
+    pub lift: f64,
+}
+
+/// An element set that no larger element set has the same support as.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClosedItemset<T: Ord + Debug + Clone> {
+    pub elements: OrderedSet<T>,
+    /// The undif strength of the mop with these elements.
+    pub strength: f64,
+    /// `strength` relative to that of all excerpts.
+    pub support: f64,
+}
+
+impl<T: Ord + Debug + Clone, S: Strength> RedundantDiscriminationTree<T, S> {
+    fn support(&self, elements: &OrderedSet<T>) -> f64 {
+        let total = self.mop(MopId::ROOT).undif_strength();
+        match self.complete_match(elements) {
+            Some(mop) if total > 0.0 => mop.undif_strength() / total,
+            _ => 0.0,
+        }
+    }
+
+    /// Association rules derived from the links between each (non root)
+    /// epitome and its children: the epitome's elements are the antecedent
+    /// and the extra elements of the child the consequent.  Only rules with
+    /// at least `min_support` and `min_confidence` are returned and they are
+    /// ordered by antecedent.
+    pub fn association_rules(
+        &self,
+        min_support: f64,
+        min_confidence: f64,
+    ) -> Vec<AssociationRule<T>> {
+        let mut rules = vec![];
+        for epitome in self.epitomes().iter() {
+            if epitome.elements().is_empty() || epitome.undif_strength() <= 0.0 {
+                continue;
+            }
+            for child in epitome.distinct_children().iter() {
+                let support = self.support(child.elements());
+                let confidence = child.undif_strength() / epitome.undif_strength();
+                if support < min_support || confidence < min_confidence {
+                    continue;
+                }
+                let consequent = child.elements() - epitome.elements();
+                let consequent_support = self.support(&consequent);
+                let lift = if consequent_support > 0.0 {
+                    confidence / consequent_support
+                } else {
+                    0.0
+                };
+                rules.push(AssociationRule {
+                    antecedent: epitome.elements().clone(),
+                    consequent,
+                    support,
+                    confidence,
+                    lift,
+                });
+            }
+        }
+        rules
+    }
+
+    /// The element sets of the traces and epitomes (other than the root)
+    /// whose undif strength is at least `min_strength`, in element order.
+    /// Every mop is the intersection of the traces below it so these are
+    /// the closed itemsets of the included excerpts and, with
+    /// `CountStrength`, `min_strength` is a minimum support count.
+    pub fn closed_itemsets(&self, min_strength: f64) -> Vec<ClosedItemset<T>> {
+        let total = self.mop(MopId::ROOT).undif_strength();
+        self.all_mops()
+            .iter()
+            .filter(|mop| !mop.elements().is_empty() && mop.undif_strength() >= min_strength)
+            .map(|mop| ClosedItemset {
+                elements: mop.elements().clone(),
+                strength: mop.undif_strength(),
+                support: if total > 0.0 {
+                    mop.undif_strength() / total
+                } else {
+                    0.0
+                },
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strength::CountStrength;
+    use crate::tests::pseudo_random_excerpts;
+
+    fn frequency(excerpts: &[OrderedSet<u8>], elements: &OrderedSet<u8>) -> f64 {
+        excerpts.iter().filter(|x| x.is_superset(elements)).count() as f64 / excerpts.len() as f64
+    }
+
+    #[test]
+    fn association_rules_agree_with_frequencies() {
+        let excerpts = pseudo_random_excerpts(60, 79);
+        let mut rdt = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        for excerpt in excerpts.iter() {
+            rdt.include_excerpt(excerpt.clone());
+        }
+        let all_rules = rdt.association_rules(0.0, 0.0);
+        assert!(!all_rules.is_empty());
+        for rule in all_rules.iter() {
+            assert!(!rule.antecedent.is_empty() && !rule.consequent.is_empty());
+            assert!(rule.antecedent.is_disjoint(&rule.consequent));
+            let mut both = rule.antecedent.clone();
+            both |= rule.consequent.clone();
+            let support = frequency(&excerpts, &both);
+            let confidence = support / frequency(&excerpts, &rule.antecedent);
+            let lift = confidence / frequency(&excerpts, &rule.consequent);
+            assert!((rule.support - support).abs() < 1e-12);
+            assert!((rule.confidence - confidence).abs() < 1e-12);
+            assert!((rule.lift - lift).abs() < 1e-12);
+        }
+        let strong_rules = rdt.association_rules(0.1, 0.5);
+        let expected: Vec<AssociationRule<u8>> = all_rules
+            .into_iter()
+            .filter(|rule| rule.support >= 0.1 && rule.confidence >= 0.5)
+            .collect();
+        assert_eq!(strong_rules, expected);
+    }
+
+    #[test]
+    fn closed_itemsets_after_exclusion() {
+        let mut rdt = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        rdt.include_excerpt(vec![1, 2].into());
+        rdt.include_excerpt(vec![1, 3].into());
+        assert!(rdt.exclude_excerpt(&vec![1, 3].into()));
+        let itemsets = rdt.closed_itemsets(0.0);
+        assert_eq!(
+            itemsets,
+            vec![ClosedItemset {
+                elements: vec![1, 2].into(),
+                strength: 1.0,
+                support: 1.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn closed_itemsets_agree_with_brute_force() {
+        let excerpts = pseudo_random_excerpts(60, 83);
+        let mut rdt = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        for excerpt in excerpts.iter() {
+            rdt.include_excerpt(excerpt.clone());
+        }
+        let count =
+            |elements: &OrderedSet<u8>| excerpts.iter().filter(|x| x.is_superset(elements)).count();
+        for min_count in [1, 2, 5, 10] {
+            let mut expected = vec![];
+            for bits in 1..=u8::MAX {
+                let elements: OrderedSet<u8> = (0..8u8)
+                    .filter(|i| bits & (1 << i) != 0)
+                    .collect::<Vec<u8>>()
+                    .into();
+                let support = count(&elements);
+                let closed = (0..8u8).filter(|i| !elements.contains(i)).all(|i| {
+                    let mut bigger = elements.clone();
+                    bigger.insert(i);
+                    count(&bigger) < support
+                });
+                if support >= min_count && closed {
+                    expected.push((elements, support));
+                }
+            }
+            expected.sort();
+            let found: Vec<(OrderedSet<u8>, usize)> = rdt
+                .closed_itemsets(min_count as f64)
+                .into_iter()
+                .map(|itemset| (itemset.elements, itemset.strength as usize))
+                .collect();
+            assert_eq!(found, expected);
+        }
+        for itemset in rdt.closed_itemsets(0.0).iter() {
+            assert_eq!(itemset.support, itemset.strength / excerpts.len() as f64);
+        }
+    }
+}
@@ -0,0 +1,572 @@
+// Copyright 2021 Peter Williams <pwil3058@gmail.com> <pwil3058@bigpond.net.au>
+
+//! Saving and loading of `RedundantDiscriminationTree`s.
+//!
+//! A tree is a DAG: the same mop is usually reachable via several real and
+//! virtual child indices.  So each mop is written exactly once, as a node
+//! record holding its elements, its three strengths, its child links
+//! (which refer to other mops by node id with the root mop being node 0)
+//! and its label strengths, and loading rebuilds the same shared structure
+//! from those records.
+//!
+//! The binary format is the magic bytes `ORDT`, a `u32` format version, the
+//! tree's decay and growth rates (as `f64`s) and a `u64` node count followed
+//! by the node records.  All numbers are little endian.
+//!
+//! With the `serde` feature the same node records (and format version) are
+//! used to implement `Serialize` and `Deserialize` for the tree.
+
+use std::fmt::Debug;
+use std::io::{self, Read, Write};
+
+use ordered_collections::{OrderedMap, OrderedSet};
+
+use crate::arena::{Arena, MopId};
+use crate::strength::{Strength, StrengthConfig};
+use crate::{Mop, RedundantDiscriminationTree};
+
+const MAGIC: &[u8; 4] = b"ORDT";
+const FORMAT_VERSION: u32 = 1;
+
+/// Types that can be written to and read back from the binary format.
+pub trait BinaryEncode: Sized {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self>;
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+macro_rules! impl_binary_encode_for_number {
+    ( $( $t:ty ),* ) => {
+        $(
+            impl BinaryEncode for $t {
+                fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+                    writer.write_all(&self.to_le_bytes())
+                }
+
+                fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+                    let mut bytes = [0u8; std::mem::size_of::<$t>()];
+                    reader.read_exact(&mut bytes)?;
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_binary_encode_for_number!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl BinaryEncode for bool {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        u8::from(*self).encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        match u8::decode(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(invalid_data(format!("{byte} is not a bool"))),
+        }
+    }
+}
+
+impl BinaryEncode for char {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        u32::from(*self).encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let code = u32::decode(reader)?;
+        char::from_u32(code).ok_or_else(|| invalid_data(format!("{code:#x} is not a char")))
+    }
+}
+
+impl BinaryEncode for String {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        encode_len(self.len(), writer)?;
+        writer.write_all(self.as_bytes())
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let len = decode_len(reader)?;
+        let mut bytes = vec![];
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        String::from_utf8(bytes).map_err(invalid_data)
+    }
+}
+
+fn encode_len<W: Write>(len: usize, writer: &mut W) -> io::Result<()> {
+    (len as u64).encode(writer)
+}
+
+fn decode_len<R: Read>(reader: &mut R) -> io::Result<usize> {
+    usize::try_from(u64::decode(reader)?).map_err(invalid_data)
+}
+
+fn encode_vec<W: Write, E: BinaryEncode>(items: &[E], writer: &mut W) -> io::Result<()> {
+    encode_len(items.len(), writer)?;
+    for item in items.iter() {
+        item.encode(writer)?;
+    }
+    Ok(())
+}
+
+// NB: the length comes from the input so it is not used to preallocate
+fn decode_vec<R: Read, E: BinaryEncode>(reader: &mut R) -> io::Result<Vec<E>> {
+    let len = decode_len(reader)?;
+    let mut items = vec![];
+    for _ in 0..len {
+        items.push(E::decode(reader)?);
+    }
+    Ok(items)
+}
+
+impl<A: BinaryEncode, B: BinaryEncode> BinaryEncode for (A, B) {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.0.encode(writer)?;
+        self.1.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok((A::decode(reader)?, B::decode(reader)?))
+    }
+}
+
+/// A flattened `Mop` with its child links given as node ids.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct NodeRecord<T, S> {
+    pub(crate) elements: Vec<T>,
+    pub(crate) trace_strength: S,
+    pub(crate) epitome_strength: S,
+    pub(crate) undif_strength: S,
+    pub(crate) children_r: Vec<(T, u32)>,
+    pub(crate) children_v: Vec<(T, u32)>,
+    pub(crate) labels: Vec<(String, S)>,
+}
+
+impl<T: BinaryEncode, S: BinaryEncode> NodeRecord<T, S> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        encode_vec(&self.elements, writer)?;
+        self.trace_strength.encode(writer)?;
+        self.epitome_strength.encode(writer)?;
+        self.undif_strength.encode(writer)?;
+        encode_vec(&self.children_r, writer)?;
+        encode_vec(&self.children_v, writer)?;
+        encode_vec(&self.labels, writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            elements: decode_vec(reader)?,
+            trace_strength: S::decode(reader)?,
+            epitome_strength: S::decode(reader)?,
+            undif_strength: S::decode(reader)?,
+            children_r: decode_vec(reader)?,
+            children_v: decode_vec(reader)?,
+            labels: decode_vec(reader)?,
+        })
+    }
+}
+
+impl<T: Ord + Debug + Clone, S: Strength> RedundantDiscriminationTree<T, S> {
+    // The node ids are the mops' positions in the arena and the strengths
+    // are rebased to time zero as a loaded tree's clock starts from zero
+    pub(crate) fn to_records(&self) -> Vec<NodeRecord<T, S>> {
+        let rebased = |strength: &S| strength.rebased(self.clock, 0, &self.config);
+        let links = |children: &OrderedMap<T, MopId>| -> Vec<(T, u32)> {
+            children
+                .iter()
+                .map(|(j, j_mop)| {
+                    let id = u32::try_from(j_mop.index()).expect("too many mops to save");
+                    (j.clone(), id)
+                })
+                .collect()
+        };
+        self.mops
+            .ids()
+            .map(|id| {
+                let mop = self.mops.get(id);
+                NodeRecord {
+                    elements: mop.elements.iter().cloned().collect(),
+                    trace_strength: rebased(&mop.trace_strength),
+                    epitome_strength: rebased(&mop.epitome_strength),
+                    undif_strength: rebased(&mop.undif_strength),
+                    children_r: links(&mop.children_r),
+                    children_v: links(&mop.children_v),
+                    labels: mop
+                        .label_strengths
+                        .iter()
+                        .map(|(label, strength)| (label.clone(), rebased(strength)))
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// Rebuild a tree from node records checking that they describe a
+    /// valid tree.
+    pub(crate) fn from_records(
+        records: Vec<NodeRecord<T, S>>,
+        config: StrengthConfig,
+    ) -> Result<Self, String> {
+        if S::USES_CONFIG {
+            if !config.is_valid() {
+                return Err("invalid decay or growth rate".to_string());
+            }
+        } else if config != StrengthConfig::of::<S>() {
+            return Err("rates not supported by this strength".to_string());
+        }
+        if records.is_empty() {
+            return Err("no root node".to_string());
+        }
+        let mut mops: Vec<Mop<T, S>> = vec![];
+        for (id, record) in records.iter().enumerate() {
+            let elements: OrderedSet<T> = record.elements.iter().collect();
+            if elements.len() != record.elements.len() {
+                return Err(format!("node {id}: duplicate elements"));
+            }
+            if id == 0 && !elements.is_empty() {
+                return Err("root node has elements".to_string());
+            }
+            let mut label_strengths = OrderedMap::<String, S>::new();
+            for (label, strength) in record.labels.iter() {
+                label_strengths.insert(label.clone(), *strength);
+            }
+            if label_strengths.len() != record.labels.len() {
+                return Err(format!("node {id}: duplicate labels"));
+            }
+            mops.push(Mop {
+                elements,
+                children_r: OrderedMap::new(),
+                children_v: OrderedMap::new(),
+                trace_strength: record.trace_strength,
+                epitome_strength: record.epitome_strength,
+                undif_strength: record.undif_strength,
+                label_strengths,
+            });
+        }
+        let mut real_parents = vec![OrderedSet::<usize>::new(); records.len()];
+        for (id, record) in records.into_iter().enumerate() {
+            for (is_real, links) in [(true, record.children_r), (false, record.children_v)] {
+                for (j, child_id) in links {
+                    let child_id = child_id as usize;
+                    if child_id == 0 || child_id >= mops.len() {
+                        return Err(format!("node {id}: bad child id {child_id}"));
+                    }
+                    let (mop, child) = (&mops[id], &mops[child_id]);
+                    if !child.elements.contains(&j)
+                        || mop.elements.contains(&j)
+                        || !child.elements.is_superset(&mop.elements)
+                    {
+                        return Err(format!("node {id}: bad link to node {child_id}"));
+                    }
+                    let child = MopId::from_index(child_id);
+                    if is_real {
+                        real_parents[child_id].insert(id);
+                        mops[id].children_r.insert(j, child);
+                    } else {
+                        mops[id].children_v.insert(j, child);
+                    }
+                }
+            }
+        }
+        for (id, parents) in real_parents.iter().enumerate().skip(1) {
+            if parents.len() != 1 {
+                return Err(format!("node {id}: has {} real parents", parents.len()));
+            }
+        }
+        let tree = Self {
+            mops: Arena::from_mops(mops),
+            config,
+            clock: 0,
+        };
+        if !tree.reachable().iter().all(|reachable| *reachable)
+            || tree.all_mops().len() != tree.mops.len()
+        {
+            return Err("unreachable or duplicate nodes".to_string());
+        }
+        if !tree.mops.ids().all(|id| tree.verify_mop(id)) || !tree.verify_tree(MopId::ROOT) {
+            return Err("invalid tree structure".to_string());
+        }
+        Ok(tree)
+    }
+}
+
+impl<T, S> RedundantDiscriminationTree<T, S>
+where
+    T: Ord + Debug + Clone + BinaryEncode,
+    S: Strength + BinaryEncode,
+{
+    /// Write the tree in the binary format.  Each mop is written once no
+    /// matter how many links lead to it.
+    pub fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        FORMAT_VERSION.encode(writer)?;
+        self.config.encode(writer)?;
+        let records = self.to_records();
+        encode_len(records.len(), writer)?;
+        for record in records.iter() {
+            record.encode(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Read a tree written by `save()`.  Malformed input is reported as an
+    /// `io::ErrorKind::InvalidData` error.
+    pub fn load<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a redundant discrimination tree"));
+        }
+        let version = u32::decode(reader)?;
+        if version != FORMAT_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported format version {version}"
+            )));
+        }
+        let config = StrengthConfig::decode(reader)?;
+        let mut records = vec![];
+        for _ in 0..decode_len(reader)? {
+            records.push(NodeRecord::decode(reader)?);
+        }
+        Self::from_records(records, config).map_err(invalid_data)
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TreeRecords<T, S> {
+    version: u32,
+    config: StrengthConfig,
+    nodes: Vec<NodeRecord<T, S>>,
+}
+
+#[cfg(feature = "serde")]
+impl<T, S> serde::Serialize for RedundantDiscriminationTree<T, S>
+where
+    T: Ord + Debug + Clone + serde::Serialize,
+    S: Strength + serde::Serialize,
+{
+    fn serialize<Z: serde::Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        TreeRecords {
+            version: FORMAT_VERSION,
+            config: self.config,
+            nodes: self.to_records(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T, S> serde::Deserialize<'de> for RedundantDiscriminationTree<T, S>
+where
+    T: Ord + Debug + Clone + serde::Deserialize<'de>,
+    S: Strength + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let records = TreeRecords::<T, S>::deserialize(deserializer)?;
+        if records.version != FORMAT_VERSION {
+            return Err(D::Error::custom(format!(
+                "unsupported format version {}",
+                records.version
+            )));
+        }
+        Self::from_records(records.nodes, records.config).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strength::{SimpleStrength, TimedStrength};
+    use crate::tests::pseudo_random_excerpts;
+
+    fn saved<T, S>(rdt: &RedundantDiscriminationTree<T, S>) -> Vec<u8>
+    where
+        T: Ord + Debug + Clone + BinaryEncode,
+        S: Strength + BinaryEncode,
+    {
+        let mut bytes = vec![];
+        rdt.save(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip_preserves_tree() {
+        let config = StrengthConfig {
+            decay_rate: 0.8,
+            growth_rate: 0.1,
+        };
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::with_config(config);
+        for (i, excerpt) in pseudo_random_excerpts(40, 3).into_iter().enumerate() {
+            if i.is_multiple_of(3) {
+                rdt.include_labelled_excerpt(excerpt, if i < 20 { "early" } else { "late" });
+            } else {
+                rdt.include_excerpt(excerpt);
+            }
+            if i.is_multiple_of(5) {
+                rdt.decrement_strengths();
+            }
+        }
+        let bytes = saved(&rdt);
+        let loaded =
+            RedundantDiscriminationTree::<u8, SimpleStrength>::load(&mut &bytes[..]).unwrap();
+        assert_eq!(loaded.config(), &config);
+        assert_eq!(loaded.to_records(), rdt.to_records());
+        assert_eq!(saved(&loaded), bytes);
+        assert_eq!(loaded.all_mops().len(), rdt.all_mops().len());
+
+        for (a, b) in loaded.traces().iter().zip(rdt.traces().iter()) {
+            assert_eq!(a.elements(), b.elements());
+            assert_eq!(a.trace_strength(), b.trace_strength());
+            assert_eq!(a.labels(), b.labels());
+            for label in b.labels().iter() {
+                assert_eq!(a.label_strength(label), b.label_strength(label));
+            }
+        }
+        assert_eq!(loaded.traces().len(), rdt.traces().len());
+        assert_eq!(loaded.epitomes().len(), rdt.epitomes().len());
+        for query in pseudo_random_excerpts(20, 11).iter() {
+            assert_eq!(
+                loaded
+                    .complete_match(query)
+                    .map(|mop| mop.elements().clone()),
+                rdt.complete_match(query).map(|mop| mop.elements().clone())
+            );
+            assert_eq!(loaded.partial_matches(query), rdt.partial_matches(query));
+        }
+    }
+
+    #[test]
+    fn round_trip_preserves_sharing() {
+        let mut rdt = RedundantDiscriminationTree::<String, SimpleStrength>::new();
+        for experience in [
+            &["a", "b", "c", "d"][..],
+            &["a", "b", "c"],
+            &["a", "b", "d"],
+            &["a", "d"],
+        ] {
+            let experience: Vec<String> = experience.iter().map(|e| e.to_string()).collect();
+            rdt.include_experience(&experience);
+        }
+        let loaded =
+            RedundantDiscriminationTree::<String, SimpleStrength>::load(&mut &saved(&rdt)[..])
+                .unwrap();
+        assert_eq!(loaded.all_mops().len(), loaded.mops.len());
+        for id in loaded.mops.ids() {
+            let mop = loaded.mops.get(id);
+            for (_, child) in mop.children_r.iter().chain(mop.children_v.iter()) {
+                let elements = loaded.mop(*child).elements();
+                assert_eq!(loaded.complete_match(elements).unwrap().id(), *child);
+            }
+        }
+        assert_eq!(loaded.traces().len(), 4);
+        assert_eq!(loaded.epitomes().len(), 6);
+    }
+
+    #[test]
+    fn round_trip_preserves_decayed_timed_strengths() {
+        let mut rdt = RedundantDiscriminationTree::<u8, TimedStrength>::new();
+        for (i, excerpt) in pseudo_random_excerpts(30, 17).into_iter().enumerate() {
+            rdt.include_excerpt(excerpt);
+            if i.is_multiple_of(3) {
+                rdt.decrement_strengths();
+            }
+        }
+        let mut loaded =
+            RedundantDiscriminationTree::<u8, TimedStrength>::load(&mut &saved(&rdt)[..]).unwrap();
+        assert_eq!(loaded.to_records(), rdt.to_records());
+        rdt.decrement_strengths();
+        loaded.decrement_strengths();
+        for (a, b) in loaded.all_mops().iter().zip(rdt.all_mops().iter()) {
+            assert_eq!(a.elements(), b.elements());
+            assert!((a.trace_strength() - b.trace_strength()).abs() < 1e-12);
+            assert!((a.undif_strength() - b.undif_strength()).abs() < 1e-12);
+        }
+        let stalled = StrengthConfig {
+            growth_rate: 0.0,
+            ..*rdt.config()
+        };
+        assert!(RedundantDiscriminationTree::from_records(rdt.to_records(), stalled).is_err());
+    }
+
+    #[test]
+    fn load_rejects_bad_input() {
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for excerpt in pseudo_random_excerpts(10, 5) {
+            rdt.include_excerpt(excerpt);
+        }
+        let bytes = saved(&rdt);
+        let load =
+            |bytes: &[u8]| RedundantDiscriminationTree::<u8, SimpleStrength>::load(&mut &bytes[..]);
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(
+            load(&bad_magic).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        let mut bad_version = bytes.clone();
+        bad_version[4] = 99;
+        assert_eq!(
+            load(&bad_version).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert!(load(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut records = rdt.to_records();
+        let (j, _) = records[0].children_r[0];
+        records[0].children_v.push((j, 1));
+        assert!(RedundantDiscriminationTree::from_records(records, *rdt.config()).is_err());
+        let mut records = rdt.to_records();
+        records[0].children_r[0].1 = records.len() as u32;
+        assert!(RedundantDiscriminationTree::from_records(records, *rdt.config()).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for (i, excerpt) in pseudo_random_excerpts(30, 13).into_iter().enumerate() {
+            if i.is_multiple_of(2) {
+                rdt.include_labelled_excerpt(excerpt, "even");
+            } else {
+                rdt.include_excerpt(excerpt);
+            }
+        }
+        let json = serde_json::to_string(&rdt).unwrap();
+        let loaded: RedundantDiscriminationTree<u8, SimpleStrength> =
+            serde_json::from_str(&json).unwrap();
+        for (a, b) in loaded.to_records().iter().zip(rdt.to_records().iter()) {
+            assert_eq!(a.elements, b.elements);
+            assert_eq!(a.children_r, b.children_r);
+            assert_eq!(a.children_v, b.children_v);
+            assert!((a.trace_strength.value() - b.trace_strength.value()).abs() < 1e-12);
+            assert!((a.undif_strength.value() - b.undif_strength.value()).abs() < 1e-12);
+            assert_eq!(a.labels.len(), b.labels.len());
+            for ((a_label, a_strength), (b_label, b_strength)) in
+                a.labels.iter().zip(b.labels.iter())
+            {
+                assert_eq!(a_label, b_label);
+                assert!((a_strength.value() - b_strength.value()).abs() < 1e-12);
+            }
+        }
+        assert_eq!(loaded.traces(), rdt.traces());
+        assert_eq!(loaded.epitomes(), rdt.epitomes());
+
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["nodes"][0]["children_r"][0][1] = serde_json::json!(0);
+        assert!(
+            serde_json::from_value::<RedundantDiscriminationTree<u8, SimpleStrength>>(value)
+                .is_err()
+        );
+    }
+}
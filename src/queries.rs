@@ -0,0 +1,529 @@
+// Copyright 2021 Peter Williams <pwil3058@gmail.com> <pwil3058@bigpond.net.au>
+
+//! Queries built on top of the basic matching algorithms.
+
+use std::cmp::Ordering;
+use std::fmt::Debug;
+
+use ordered_collections::{OrderedMap, OrderedSet};
+
+use crate::arena::MopId;
+use crate::iterators::Pending;
+use crate::strength::Strength;
+use crate::{MopRef, RedundantDiscriminationTree};
+
+/// Measures of the similarity of two element sets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Similarity {
+    /// |A ∩ B| / |A ∪ B|
+    Jaccard,
+    /// 2|A ∩ B| / (|A| + |B|)
+    Dice,
+    /// |A ∩ B| / min(|A|, |B|)
+    Overlap,
+}
+
+impl Similarity {
+    fn for_counts(&self, a_len: usize, b_len: usize, shared: usize) -> f64 {
+        let denominator = match self {
+            Similarity::Jaccard => a_len + b_len - shared,
+            Similarity::Dice => a_len + b_len,
+            Similarity::Overlap => a_len.min(b_len),
+        };
+        if denominator == 0 {
+            0.0
+        } else if *self == Similarity::Dice {
+            (2 * shared) as f64 / denominator as f64
+        } else {
+            shared as f64 / denominator as f64
+        }
+    }
+
+    pub fn of<T: Ord + Debug + Clone>(&self, a: &OrderedSet<T>, b: &OrderedSet<T>) -> f64 {
+        self.for_counts(a.len(), b.len(), a.intersection(b).count())
+    }
+
+    // The best similarity to `query` that any superset of `mop` could have.
+    // The best such superset is `mop`'s elements plus the rest of `query`.
+    fn upper_bound<T: Ord + Debug + Clone>(
+        &self,
+        mop: &OrderedSet<T>,
+        query: &OrderedSet<T>,
+    ) -> f64 {
+        let shared = mop.intersection(query).count();
+        self.for_counts(mop.len() + query.len() - shared, query.len(), query.len())
+    }
+}
+
+// Algorithm B.8 restricted to the children (reached via indices in the
+// query) whose elements are all in the query.  The traces below any other
+// child contain that child's elements and so cannot be subsets of the query.
+fn subset_traces_after<'a, T: Ord + Debug + Clone, S: Strength>(
+    tree: &'a RedundantDiscriminationTree<T, S>,
+    id: MopId,
+    query: &OrderedSet<T>,
+    k: Option<&T>,
+) -> OrderedSet<MopRef<'a, T, S>> {
+    let mut big_s = OrderedSet::default();
+    let mop = tree.mop(id);
+    if mop.is_trace() {
+        big_s.insert(mop);
+    }
+    for j in query.difference(mop.elements()) {
+        if k.is_some_and(|k| j <= k) {
+            continue;
+        }
+        if let Some(j_mop) = tree.get_child(id, j) {
+            let j_elements = tree.mop(j_mop).elements();
+            if j_elements.is_subset(query)
+                && Some(j) == j_elements.difference(mop.elements()).next()
+            {
+                big_s |= subset_traces_after(tree, j_mop, query, Some(j));
+            }
+        }
+    }
+    big_s
+}
+
+/// A partial match scored against the query that found it.
+#[derive(Debug, Clone)]
+pub struct RankedMatch<'a, T: Ord + Debug + Clone, S: Strength> {
+    pub mop: MopRef<'a, T, S>,
+    /// The Jaccard similarity of the query and the mop's elements.
+    pub overlap: f64,
+    /// `overlap` weighted by the mop's undif strength.
+    pub score: f64,
+    /// Query elements that the mop lacks.
+    pub missing: OrderedSet<T>,
+    /// Mop elements that are not in the query.
+    pub extra: OrderedSet<T>,
+}
+
+impl<T: Ord + Debug + Clone, S: Strength> RedundantDiscriminationTree<T, S> {
+    /// The (at most) `k` best of `partial_matches(query)` best first.  A
+    /// match's score is its overlap with the query (Jaccard similarity)
+    /// multiplied by its undif strength i.e. the strength of all the
+    /// excerpts that contain it.  Ties are broken by the mops' ordering.
+    pub fn ranked_partial_matches(
+        &self,
+        query: &OrderedSet<T>,
+        k: usize,
+    ) -> Vec<RankedMatch<'_, T, S>> {
+        let mut matches: Vec<RankedMatch<'_, T, S>> = self
+            .iter_partial_matches(query)
+            .map(|mop| {
+                let overlap = Similarity::Jaccard.of(query, mop.elements());
+                RankedMatch {
+                    overlap,
+                    score: overlap * mop.undif_strength(),
+                    missing: query - mop.elements(),
+                    extra: mop.elements() - query,
+                    mop,
+                }
+            })
+            .collect();
+        matches.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.mop.cmp(&b.mop))
+        });
+        matches.truncate(k);
+        matches
+    }
+
+    /// The (at most) `k` traces most similar to `query` under `metric`,
+    /// most similar first, with their similarities.  Traces that have no
+    /// elements in common with `query` are never included.  Every trace
+    /// below a mop contains that mop's elements so the traversal (as per
+    /// algorithm B.8) skips any mop that could not lead to a trace that
+    /// beats the current `k`th best.
+    pub fn nearest_traces(
+        &self,
+        query: &OrderedSet<T>,
+        k: usize,
+        metric: Similarity,
+    ) -> Vec<(MopRef<'_, T, S>, f64)> {
+        let mut best: Vec<(MopRef<'_, T, S>, f64)> = vec![];
+        if k == 0 {
+            return best;
+        }
+        let mut stack: Vec<Pending<T>> = vec![(MopId::ROOT, None)];
+        while let Some((id, after)) = stack.pop() {
+            let mop = self.mop(id);
+            let threshold = if best.len() == k { best[k - 1].1 } else { 0.0 };
+            if metric.upper_bound(mop.elements(), query) < threshold {
+                continue;
+            }
+            if mop.is_trace() {
+                let similarity = metric.of(mop.elements(), query);
+                if similarity > 0.0 && similarity >= threshold {
+                    best.push((mop, similarity));
+                    best.sort_by(|a, b| {
+                        b.1.partial_cmp(&a.1)
+                            .unwrap_or(Ordering::Equal)
+                            .then_with(|| a.0.cmp(&b.0))
+                    });
+                    best.truncate(k);
+                }
+            }
+            for (j, j_mop) in self.first_index_children(id, after.as_ref()) {
+                stack.push((j_mop, Some(j.clone())));
+            }
+        }
+        best
+    }
+
+    /// The (at most) `k` elements most likely to occur along with those of
+    /// `query`, most likely first.  The traces that contain `query` (i.e.
+    /// those at or below its complete match) are weighted by their trace
+    /// strengths and an element's score is the weighted fraction of them
+    /// that contain it.  So, with `CountStrength`, it is the proportion of
+    /// the excerpts containing `query` that also contain the element.
+    pub fn predict_completions(&self, query: &OrderedSet<T>, k: usize) -> Vec<(T, f64)> {
+        let mut total = 0.0;
+        let mut weights = OrderedMap::<T, f64>::new();
+        for trace in self.superset_traces(query).iter() {
+            let strength = trace.trace_strength();
+            total += strength;
+            for element in trace.elements().difference(query) {
+                match weights.get_mut(element) {
+                    Some(weight) => *weight += strength,
+                    None => {
+                        weights.insert(element.clone(), strength);
+                    }
+                }
+            }
+        }
+        if total <= 0.0 {
+            return vec![];
+        }
+        let mut completions: Vec<(T, f64)> = weights
+            .iter()
+            .map(|(element, weight)| (element.clone(), weight / total))
+            .collect();
+        completions.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        completions.truncate(k);
+        completions
+    }
+
+    /// The labels of the excerpts that resemble `query` with scores that
+    /// sum to one, best first.  If `query` has a complete match the scores
+    /// are in proportion to that mop's label strengths (i.e. to those of the
+    /// labelled excerpts that contain `query`).  Otherwise the label strengths
+    /// of the partial matches are combined weighted by their overlap with
+    /// `query` (Jaccard similarity).
+    pub fn class_scores(&self, query: &OrderedSet<T>) -> Vec<(String, f64)> {
+        let mut totals = OrderedMap::<String, f64>::new();
+        let mut add = |mop: &MopRef<'_, T, S>, weight: f64| {
+            for label in mop.labels() {
+                let score = weight * mop.label_strength(&label);
+                match totals.get_mut(&label) {
+                    Some(total) => *total += score,
+                    None => {
+                        totals.insert(label, score);
+                    }
+                }
+            }
+        };
+        match self.complete_match(query) {
+            Some(mop) => add(&mop, 1.0),
+            None => {
+                for mop in self.iter_partial_matches(query) {
+                    add(&mop, Similarity::Jaccard.of(query, mop.elements()));
+                }
+            }
+        }
+        let sum: f64 = totals.iter().map(|(_, total)| total).sum();
+        if sum <= 0.0 {
+            return vec![];
+        }
+        let mut scores: Vec<(String, f64)> = totals
+            .iter()
+            .map(|(label, total)| (label.clone(), total / sum))
+            .collect();
+        scores.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        scores
+    }
+
+    /// The best scoring label for `query` (see `class_scores()`) if any.
+    pub fn classify(&self, query: &OrderedSet<T>) -> Option<String> {
+        self.class_scores(query)
+            .into_iter()
+            .next()
+            .map(|(label, _)| label)
+    }
+
+    /// How surprising `excerpt` would be if it were included: one minus
+    /// the best explanation that the tree has for it.  An explanation is
+    /// the complete match or one of the partial matches of `excerpt` and it
+    /// is the fraction of `excerpt`'s elements that it covers times its undif
+    /// strength relative to that of the root (i.e. the support for those
+    /// elements).  So the score is zero for an excerpt whose elements occur
+    /// in every excerpt included so far and one for an excerpt whose elements
+    /// have never been seen (or if nothing has been included).  The tree is
+    /// not changed.
+    pub fn novelty(&self, excerpt: &OrderedSet<T>) -> f64 {
+        let total = self.mop(MopId::ROOT).undif_strength();
+        if total <= 0.0 {
+            return 1.0;
+        }
+        let mut familiarity = match self.complete_match(excerpt) {
+            Some(mop) => mop.undif_strength() / total,
+            None => 0.0,
+        };
+        for mop in self.iter_partial_matches(excerpt) {
+            let coverage =
+                mop.elements().intersection(excerpt).count() as f64 / excerpt.len() as f64;
+            familiarity = familiarity.max(coverage * mop.undif_strength() / total);
+        }
+        1.0 - familiarity.min(1.0)
+    }
+
+    /// All traces whose elements are all in `query`.
+    pub fn subset_traces(&self, query: &OrderedSet<T>) -> OrderedSet<MopRef<'_, T, S>> {
+        subset_traces_after(self, MopId::ROOT, query, None)
+    }
+
+    /// All traces whose elements include those of `query`.  These are the
+    /// traces at or below the mop found by `complete_match(query)` and they
+    /// are collected using algorithm B.8 so none is visited twice.
+    pub fn superset_traces(&self, query: &OrderedSet<T>) -> OrderedSet<MopRef<'_, T, S>> {
+        match self.complete_match(query) {
+            Some(mop) => mop.traces(),
+            None => OrderedSet::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strength::{CountStrength, SimpleStrength};
+    use crate::tests::pseudo_random_excerpts;
+
+    #[test]
+    fn ranked_partial_matches_are_scored_and_ordered() {
+        let mut rdt = RedundantDiscriminationTree::<&str, SimpleStrength>::new();
+        rdt.include_experience(&["a", "b", "c"]);
+        rdt.include_experience(&["a", "b", "c"]);
+        rdt.include_experience(&["a", "d"]);
+        rdt.include_experience(&["e", "f"]);
+        let query: OrderedSet<&str> = vec!["a", "b", "e"].into();
+        let ranked = rdt.ranked_partial_matches(&query, 10);
+        assert_eq!(ranked.len(), rdt.partial_matches(&query).len());
+        let best = &ranked[0];
+        assert_eq!(best.mop.elements(), &OrderedSet::from(vec!["a", "b", "c"]));
+        assert_eq!(best.overlap, 0.5);
+        assert_eq!(best.missing, OrderedSet::from(vec!["e"]));
+        assert_eq!(best.extra, OrderedSet::from(vec!["c"]));
+        assert_eq!(rdt.ranked_partial_matches(&query, 1).len(), 1);
+        assert!(rdt.ranked_partial_matches(&query, 0).is_empty());
+    }
+
+    #[test]
+    fn ranked_partial_matches_agree_with_partial_matches() {
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for excerpt in pseudo_random_excerpts(50, 43) {
+            rdt.include_excerpt(excerpt);
+        }
+        for query in pseudo_random_excerpts(20, 47).iter() {
+            let ranked = rdt.ranked_partial_matches(query, usize::MAX);
+            let mops: OrderedSet<MopRef<'_, u8, SimpleStrength>> =
+                ranked.iter().map(|m| &m.mop).collect();
+            assert_eq!(mops, rdt.partial_matches(query));
+            for pair in ranked.windows(2) {
+                assert!(pair[0].score >= pair[1].score);
+            }
+            for m in ranked.iter() {
+                assert_eq!(m.score, m.overlap * m.mop.undif_strength());
+                assert!(m.overlap > 0.0);
+                assert!(m.missing.is_disjoint(m.mop.elements()));
+                assert!(m.extra.is_disjoint(query));
+            }
+        }
+    }
+
+    #[test]
+    fn superset_traces_agree_with_brute_force() {
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        assert!(rdt.superset_traces(&vec![1].into()).is_empty());
+        for excerpt in pseudo_random_excerpts(50, 53) {
+            rdt.include_excerpt(excerpt);
+        }
+        let mut queries = pseudo_random_excerpts(30, 59);
+        queries.push(OrderedSet::new());
+        queries.push(vec![0, 1, 2, 3, 4, 5, 6, 7].into());
+        for query in queries.iter() {
+            let expected: OrderedSet<MopRef<'_, u8, SimpleStrength>> = rdt
+                .traces()
+                .iter()
+                .filter(|mop| mop.elements().is_superset(query))
+                .collect();
+            assert_eq!(rdt.superset_traces(query), expected);
+        }
+    }
+
+    #[test]
+    fn subset_traces_agree_with_brute_force() {
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        assert!(rdt.subset_traces(&vec![1].into()).is_empty());
+        for excerpt in pseudo_random_excerpts(50, 61) {
+            rdt.include_excerpt(excerpt);
+        }
+        let mut queries = pseudo_random_excerpts(30, 67);
+        queries.push(OrderedSet::new());
+        queries.push(vec![0, 1, 2, 3, 4, 5, 6, 7].into());
+        for query in queries.iter() {
+            let expected: OrderedSet<MopRef<'_, u8, SimpleStrength>> = rdt
+                .traces()
+                .iter()
+                .filter(|mop| mop.elements().is_subset(query))
+                .collect();
+            assert_eq!(rdt.subset_traces(query), expected);
+        }
+        assert_eq!(
+            rdt.subset_traces(&vec![0, 1, 2, 3, 4, 5, 6, 7].into()),
+            rdt.traces()
+        );
+    }
+
+    #[test]
+    fn nearest_traces_agree_with_brute_force() {
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for excerpt in pseudo_random_excerpts(60, 71) {
+            rdt.include_excerpt(excerpt);
+        }
+        for metric in [Similarity::Jaccard, Similarity::Dice, Similarity::Overlap] {
+            for query in pseudo_random_excerpts(20, 73).iter() {
+                for k in [0, 1, 3, 10, 100] {
+                    let mut expected: Vec<(MopRef<'_, u8, SimpleStrength>, f64)> = rdt
+                        .traces()
+                        .iter()
+                        .map(|mop| (*mop, metric.of(mop.elements(), query)))
+                        .filter(|(_, similarity)| *similarity > 0.0)
+                        .collect();
+                    expected
+                        .sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+                    expected.truncate(k);
+                    assert_eq!(rdt.nearest_traces(query, k, metric), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn similarity_measures() {
+        let a: OrderedSet<u8> = vec![1, 2, 3, 4].into();
+        let b: OrderedSet<u8> = vec![3, 4, 5].into();
+        assert_eq!(Similarity::Jaccard.of(&a, &b), 0.4);
+        assert_eq!(Similarity::Dice.of(&a, &b), 4.0 / 7.0);
+        assert_eq!(Similarity::Overlap.of(&a, &b), 2.0 / 3.0);
+        assert_eq!(Similarity::Jaccard.of(&a, &OrderedSet::new()), 0.0);
+    }
+
+    #[test]
+    fn predict_completions_are_conditional_frequencies() {
+        let excerpts = pseudo_random_excerpts(60, 89);
+        let mut rdt = RedundantDiscriminationTree::<u8, CountStrength>::new();
+        assert!(rdt.predict_completions(&vec![1].into(), 3).is_empty());
+        for excerpt in excerpts.iter() {
+            rdt.include_excerpt(excerpt.clone());
+        }
+        for query in pseudo_random_excerpts(20, 97).iter() {
+            let containing: Vec<&OrderedSet<u8>> =
+                excerpts.iter().filter(|x| x.is_superset(query)).collect();
+            let mut expected: Vec<(u8, f64)> = (0..8u8)
+                .filter(|e| !query.contains(e))
+                .map(|e| {
+                    let count = containing.iter().filter(|x| x.contains(&e)).count();
+                    (e, count as f64 / containing.len() as f64)
+                })
+                .filter(|(_, score)| *score > 0.0)
+                .collect();
+            expected.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+            expected.truncate(3);
+            let found = rdt.predict_completions(query, 3);
+            assert_eq!(found.len(), expected.len());
+            for (a, b) in found.iter().zip(expected.iter()) {
+                assert_eq!(a.0, b.0);
+                assert!((a.1 - b.1).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn classify_from_complete_and_partial_matches() {
+        let mut rdt = RedundantDiscriminationTree::<&str, CountStrength>::new();
+        assert!(rdt.classify(&vec!["apple"].into()).is_none());
+        rdt.include_labelled_excerpt(vec!["apple", "banana"].into(), "fruit");
+        rdt.include_labelled_excerpt(vec!["apple", "cherry"].into(), "fruit");
+        rdt.include_labelled_excerpt(vec!["carrot", "leek"].into(), "veg");
+        rdt.include_excerpt(vec!["apple", "leek"].into());
+        assert_eq!(
+            rdt.classify(&vec!["apple"].into()),
+            Some("fruit".to_string())
+        );
+        assert_eq!(rdt.classify(&vec!["leek"].into()), Some("veg".to_string()));
+        assert_eq!(
+            rdt.class_scores(&OrderedSet::new()),
+            vec![
+                ("fruit".to_string(), 2.0 / 3.0),
+                ("veg".to_string(), 1.0 / 3.0)
+            ]
+        );
+        assert!(rdt
+            .complete_match(&vec!["apple", "banana", "carrot", "zzz"].into())
+            .is_none());
+        assert_eq!(
+            rdt.classify(&vec!["apple", "banana", "carrot", "zzz"].into()),
+            Some("fruit".to_string())
+        );
+        assert_eq!(
+            rdt.classify(&vec!["carrot", "leek", "zzz"].into()),
+            Some("veg".to_string())
+        );
+        assert!(rdt.classify(&vec!["zzz"].into()).is_none());
+    }
+
+    #[test]
+    fn novelty_is_unexplained_support() {
+        let mut rdt = RedundantDiscriminationTree::<&str, CountStrength>::new();
+        assert_eq!(rdt.novelty(&vec!["a"].into()), 1.0);
+        for _ in 0..3 {
+            rdt.include_experience(&["a", "b", "c"]);
+        }
+        rdt.include_experience(&["a", "d"]);
+        assert_eq!(rdt.novelty(&OrderedSet::new()), 0.0);
+        assert_eq!(rdt.novelty(&vec!["a"].into()), 0.0);
+        assert_eq!(rdt.novelty(&vec!["a", "b"].into()), 0.25);
+        assert_eq!(rdt.novelty(&vec!["a", "d"].into()), 0.75);
+        assert_eq!(rdt.novelty(&vec!["b", "e"].into()), 0.625);
+        assert_eq!(rdt.novelty(&vec!["e"].into()), 1.0);
+    }
+
+    #[test]
+    fn novelty_does_not_change_the_tree() {
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for excerpt in pseudo_random_excerpts(50, 107) {
+            rdt.include_excerpt(excerpt);
+        }
+        let records = rdt.to_records();
+        for query in pseudo_random_excerpts(30, 109).iter() {
+            let novelty = rdt.novelty(query);
+            assert!((0.0..=1.0).contains(&novelty));
+            if rdt.complete_match(query).is_some() {
+                assert!(novelty < 1.0);
+            }
+        }
+        assert_eq!(rdt.to_records(), records);
+    }
+}
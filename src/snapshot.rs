@@ -0,0 +1,128 @@
+// Copyright 2021 Peter Williams <pwil3058@gmail.com> <pwil3058@bigpond.net.au>
+
+//! Immutable views of a tree so that queries can be answered (on other
+//! threads if need be) while new excerpts are being included.
+//!
+//! The tree's mop arena is copy on write so taking a snapshot is O(1) and
+//! the snapshot shares every mop with the live tree until a change to the
+//! tree modifies that mop, at which point the tree gets its own copy.
+
+use std::fmt::Debug;
+use std::ops::Deref;
+
+use crate::strength::Strength;
+use crate::RedundantDiscriminationTree;
+
+/// The tree as it was when `snapshot()` was called.  It derefs to the tree
+/// so all of the queries are available but none of the changes.
+#[derive(Debug, Clone)]
+pub struct Snapshot<T: Ord + Debug + Clone, S: Strength> {
+    tree: RedundantDiscriminationTree<T, S>,
+}
+
+impl<T: Ord + Debug + Clone, S: Strength> Deref for Snapshot<T, S> {
+    type Target = RedundantDiscriminationTree<T, S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tree
+    }
+}
+
+impl<T: Ord + Debug + Clone, S: Strength> RedundantDiscriminationTree<T, S> {
+    /// An immutable view of the tree as it is now.  Later changes to the
+    /// tree do not affect the snapshot.
+    pub fn snapshot(&self) -> Snapshot<T, S> {
+        Snapshot { tree: self.clone() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strength::{SimpleStrength, TimedStrength};
+    use crate::tests::pseudo_random_excerpts;
+    use ordered_collections::OrderedSet;
+
+    fn partial_matches(
+        tree: &RedundantDiscriminationTree<String, SimpleStrength>,
+        query: &OrderedSet<String>,
+    ) -> Vec<OrderedSet<String>> {
+        tree.partial_matches(query)
+            .iter()
+            .map(|mop| mop.elements().clone())
+            .collect()
+    }
+
+    #[test]
+    fn snapshots_are_isolated() {
+        let mut rdt = RedundantDiscriminationTree::<String, SimpleStrength>::new();
+        let excerpts: Vec<OrderedSet<String>> = [&["a", "b", "c"][..], &["a", "d"], &["e", "f"]]
+            .iter()
+            .map(|v| v.iter().map(|e| e.to_string()).collect::<Vec<_>>())
+            .map(|v| v.iter().collect())
+            .collect();
+        rdt.include_excerpt(excerpts[0].clone());
+        rdt.include_excerpt(excerpts[1].clone());
+        let snapshot = rdt.snapshot();
+
+        rdt.include_excerpt(excerpts[2].clone());
+        assert_eq!(snapshot.traces().len(), 2);
+        assert_eq!(rdt.traces().len(), 3);
+        assert!(snapshot.complete_match(&excerpts[2]).is_none());
+        assert!(rdt.complete_match(&excerpts[2]).is_some());
+
+        // The mop for ["a", "b", "c"] was not touched so it is still shared
+        let id = snapshot.complete_match(&excerpts[0]).unwrap().id();
+        assert_eq!(rdt.complete_match(&excerpts[0]).unwrap().id(), id);
+        assert!(snapshot.mops.shares(&rdt.mops, id));
+
+        let query: OrderedSet<String> = vec!["a".to_string(), "e".to_string()].into();
+        let expected = partial_matches(&snapshot, &query);
+        let handle = std::thread::spawn(move || partial_matches(&snapshot, &query));
+        rdt.include_excerpt(excerpts[0].clone());
+        assert_eq!(handle.join().unwrap(), expected);
+    }
+
+    #[test]
+    fn snapshots_survive_pruning() {
+        let excerpts = pseudo_random_excerpts(40, 13);
+        let mut rdt = RedundantDiscriminationTree::<u8, SimpleStrength>::new();
+        for excerpt in excerpts.iter() {
+            rdt.include_excerpt(excerpt.clone());
+        }
+        let snapshot = rdt.snapshot();
+        let records = snapshot.to_records();
+        for excerpt in excerpts.iter().step_by(2) {
+            rdt.exclude_excerpt(excerpt);
+        }
+        for _ in 0..10 {
+            rdt.decrement_strengths();
+        }
+        rdt.prune(0.5);
+        assert!(rdt.traces().len() < snapshot.traces().len());
+        assert_eq!(snapshot.to_records(), records);
+    }
+
+    #[test]
+    fn snapshots_do_not_decay() {
+        let mut rdt = RedundantDiscriminationTree::<u8, TimedStrength>::new();
+        for excerpt in pseudo_random_excerpts(30, 19) {
+            rdt.include_excerpt(excerpt);
+        }
+        let snapshot = rdt.snapshot();
+        let strengths = |tree: &RedundantDiscriminationTree<u8, TimedStrength>| -> Vec<f64> {
+            tree.all_mops()
+                .iter()
+                .map(|mop| mop.undif_strength())
+                .collect()
+        };
+        let before = strengths(&snapshot);
+        for _ in 0..5 {
+            rdt.decrement_strengths();
+        }
+        assert_eq!(strengths(&snapshot), before);
+        for (now, then) in strengths(&rdt).iter().zip(before.iter()) {
+            assert!(now < then);
+        }
+    }
+}
@@ -1,14 +1,105 @@
 // Copyright 2021 Peter Williams <pwil3058@gmail.com> <pwil3058@bigpond.net.au>
 
+use std::cmp::Ordering;
+use std::io::{self, Read, Write};
+
+use crate::persist::BinaryEncode;
+
+/// Decay and growth rates chosen when a tree is built rather than fixed by
+/// a `Strength`'s associated constants.  Only strengths whose `USES_CONFIG`
+/// is `true` honour it so trees of other strengths only accept the rates
+/// given by the associated constants.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StrengthConfig {
+    pub decay_rate: f64,
+    pub growth_rate: f64,
+}
+
+impl StrengthConfig {
+    /// The rates given by `S`'s associated constants.
+    pub fn of<S: Strength>() -> Self {
+        Self {
+            decay_rate: S::DECAY_RATE,
+            growth_rate: S::GROWTH_RATE,
+        }
+    }
+
+    /// Whether the decay rate is between 0.0 and 1.0 and the growth rate is
+    /// above 0.0 (so that inclusion always increases strengths) and at most
+    /// 1.0.  Only required of the configs of strengths that use them.
+    pub fn is_valid(&self) -> bool {
+        (0.0..=1.0).contains(&self.decay_rate) && self.growth_rate > 0.0 && self.growth_rate <= 1.0
+    }
+}
+
 pub trait Strength: Clone + Copy + PartialEq + PartialOrd + Default {
     const DECAY_RATE: f64;
     const GROWTH_RATE: f64 = 1.0 - Self::DECAY_RATE;
+    /// Whether the strength decays by itself as the tree's clock ticks (see
+    /// `value_at()`) rather than by calls to `decrease()`.
+    const DECAYS_LAZILY: bool = false;
+    /// Whether the strength grows and decays at the rates in the tree's
+    /// `StrengthConfig` (see the `*_with_config()` methods).
+    const USES_CONFIG: bool = false;
 
     fn new(incr_value: bool) -> Self;
     fn value(&self) -> f64;
     fn increase(&mut self);
     fn decrease(&mut self);
 
+    /// Increase the strength as if by `weight` calls to `increase()`.  This
+    /// default rounds `weight` to a whole number of calls so implementations
+    /// that can handle fractional weights should override it.
+    fn increase_by(&mut self, weight: f64) {
+        for _ in 0..weight.round() as u64 {
+            self.increase();
+        }
+    }
+
+    fn increase_by_with_config(&mut self, weight: f64, _config: &StrengthConfig) {
+        self.increase_by(weight);
+    }
+
+    fn decrease_with_config(&mut self, _config: &StrengthConfig) {
+        self.decrease();
+    }
+
+    /// The value of the strength when the clock of the tree that it belongs
+    /// to reads `now`.  Strengths that decay lazily override this.
+    fn value_at(&self, _now: u64, _config: &StrengthConfig) -> f64 {
+        self.value()
+    }
+
+    /// Increase the strength by `weight` when the tree's clock reads `now`.
+    fn increase_at(&mut self, weight: f64, _now: u64, config: &StrengthConfig) {
+        self.increase_by_with_config(weight, config);
+    }
+
+    /// The strength with the value it has at time `from` but as if it had
+    /// been set at time `to` e.g. to move it to another tree's clock.
+    fn rebased(&self, _from: u64, _to: u64, _config: &StrengthConfig) -> Self {
+        *self
+    }
+
+    /// The strength that `self` would have without the increases that gave
+    /// `other` (e.g. to retract an excerpt) where both are as of the same
+    /// time.  This default cannot undo increases so returns `self`.
+    fn without(&self, _other: &Self) -> Self {
+        *self
+    }
+
+    /// The strength of something that has strength `self` in one tree and
+    /// `other` in another when the trees are merged.  This default takes the
+    /// stronger of the two.
+    fn combine(&self, other: &Self) -> Self {
+        if self >= other {
+            *self
+        } else {
+            *other
+        }
+    }
+
     fn incremented(&self) -> Self {
         let mut strength = *self;
         strength.increase();
@@ -20,15 +111,23 @@ pub trait Strength: Clone + Copy + PartialEq + PartialOrd + Default {
         strength.decrease();
         strength
     }
+
+    fn incremented_at(&self, weight: f64, now: u64, config: &StrengthConfig) -> Self {
+        let mut strength = *self;
+        strength.increase_at(weight, now, config);
+        strength
+    }
 }
 
 // SIMPLE STRENGTH
 
 #[derive(Debug, Default, Clone, Copy, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SimpleStrength(f64);
 
 impl Strength for SimpleStrength {
     const DECAY_RATE: f64 = 0.95;
+    const USES_CONFIG: bool = true;
 
     fn new(incr_value: bool) -> Self {
         let mut ss = Self::default();
@@ -49,4 +148,243 @@ impl Strength for SimpleStrength {
     fn decrease(&mut self) {
         self.0 *= Self::DECAY_RATE;
     }
+
+    fn increase_by(&mut self, weight: f64) {
+        self.increase_by_with_config(weight, &StrengthConfig::of::<Self>());
+    }
+
+    fn increase_by_with_config(&mut self, weight: f64, config: &StrengthConfig) {
+        self.0 += (1.0 - self.0) * (1.0 - (1.0 - config.growth_rate).powf(weight));
+    }
+
+    fn decrease_with_config(&mut self, config: &StrengthConfig) {
+        self.0 *= config.decay_rate;
+    }
+
+    // The proportion of the gap to full strength left by both strengths so
+    // increases made in either tree count as if they had been made in one.
+    fn combine(&self, other: &Self) -> Self {
+        Self(1.0 - (1.0 - self.0) * (1.0 - other.0))
+    }
+
+    // The inverse of `combine()` (ignoring any decay in between)
+    fn without(&self, other: &Self) -> Self {
+        if other.0 >= 1.0 {
+            Self(0.0)
+        } else {
+            Self((1.0 - (1.0 - self.0) / (1.0 - other.0)).clamp(0.0, 1.0))
+        }
+    }
+}
+
+impl BinaryEncode for SimpleStrength {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.0.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self(f64::decode(reader)?))
+    }
+}
+
+impl BinaryEncode for StrengthConfig {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.decay_rate.encode(writer)?;
+        self.growth_rate.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            decay_rate: f64::decode(reader)?,
+            growth_rate: f64::decode(reader)?,
+        })
+    }
+}
+
+// COUNT STRENGTH
+
+/// An exact count of the number of times a strength has been increased.
+/// Counts do not decay so `decrease()` does nothing.
+#[derive(Debug, Default, Clone, Copy, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CountStrength(u64);
+
+impl CountStrength {
+    pub fn count(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Strength for CountStrength {
+    const DECAY_RATE: f64 = 1.0;
+
+    fn new(incr_value: bool) -> Self {
+        Self(u64::from(incr_value))
+    }
+
+    fn value(&self) -> f64 {
+        self.0 as f64
+    }
+
+    fn increase(&mut self) {
+        self.0 += 1;
+    }
+
+    fn decrease(&mut self) {}
+
+    /// Panics, leaving the count unchanged, unless `weight` is finite and
+    /// rounds to at least one increase that does not overflow the count.
+    fn increase_by(&mut self, weight: f64) {
+        assert!(
+            weight.is_finite() && weight.round() >= 1.0 && weight.round() < u64::MAX as f64,
+            "count weights must be finite and round to at least one"
+        );
+        self.0 = self
+            .0
+            .checked_add(weight.round() as u64)
+            .expect("count overflow");
+    }
+
+    fn without(&self, other: &Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Self(self.0.checked_add(other.0).expect("count overflow"))
+    }
+}
+
+impl BinaryEncode for CountStrength {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.0.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self(u64::decode(reader)?))
+    }
+}
+
+// TIMED STRENGTH
+
+/// A strength that decays by the decay rate for each tick of the clock of
+/// the tree that it belongs to.  The decay is applied lazily, when the
+/// strength is read or increased, so
+/// `RedundantDiscriminationTree::decrement_strengths()` only has to tick the
+/// tree's clock instead of visiting every mop.  `value()` is the value as
+/// of the last change to the strength and `value_at()` the decayed value.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TimedStrength {
+    value: f64,
+    stamp: u64,
+}
+
+impl PartialEq for TimedStrength {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl PartialOrd for TimedStrength {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.value.partial_cmp(&other.value)
+    }
+}
+
+impl TimedStrength {
+    fn bring_up_to_date(&mut self, now: u64, config: &StrengthConfig) {
+        self.value = self.value_at(now, config);
+        self.stamp = now;
+    }
+}
+
+impl Strength for TimedStrength {
+    const DECAY_RATE: f64 = 0.95;
+    const DECAYS_LAZILY: bool = true;
+    const USES_CONFIG: bool = true;
+
+    fn new(incr_value: bool) -> Self {
+        let mut ts = Self::default();
+        if incr_value {
+            ts.increase()
+        }
+        ts
+    }
+
+    fn value(&self) -> f64 {
+        self.value
+    }
+
+    fn increase(&mut self) {
+        self.value += (1.0 - self.value) * Self::GROWTH_RATE;
+    }
+
+    fn decrease(&mut self) {
+        self.value *= Self::DECAY_RATE;
+    }
+
+    fn increase_by(&mut self, weight: f64) {
+        self.increase_by_with_config(weight, &StrengthConfig::of::<Self>());
+    }
+
+    fn increase_by_with_config(&mut self, weight: f64, config: &StrengthConfig) {
+        self.value += (1.0 - self.value) * (1.0 - (1.0 - config.growth_rate).powf(weight));
+    }
+
+    fn decrease_with_config(&mut self, config: &StrengthConfig) {
+        self.value *= config.decay_rate;
+    }
+
+    fn value_at(&self, now: u64, config: &StrengthConfig) -> f64 {
+        let elapsed = now.saturating_sub(self.stamp);
+        self.value * config.decay_rate.powf(elapsed as f64)
+    }
+
+    fn increase_at(&mut self, weight: f64, now: u64, config: &StrengthConfig) {
+        self.bring_up_to_date(now, config);
+        self.increase_by_with_config(weight, config);
+    }
+
+    fn rebased(&self, from: u64, to: u64, config: &StrengthConfig) -> Self {
+        Self {
+            value: self.value_at(from, config),
+            stamp: to,
+        }
+    }
+
+    // As for `SimpleStrength` so the strengths should have been rebased to
+    // the same time
+    fn without(&self, other: &Self) -> Self {
+        let value = if other.value >= 1.0 {
+            0.0
+        } else {
+            (1.0 - (1.0 - self.value) / (1.0 - other.value)).clamp(0.0, 1.0)
+        };
+        Self {
+            value,
+            stamp: self.stamp.max(other.stamp),
+        }
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Self {
+            value: 1.0 - (1.0 - self.value) * (1.0 - other.value),
+            stamp: self.stamp.max(other.stamp),
+        }
+    }
+}
+
+// NB: the time stamp is relative to the tree's clock so trees rebase their
+// strengths to time zero before saving them and restart their clocks from
+// zero when they are loaded
+impl BinaryEncode for TimedStrength {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.value.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            value: f64::decode(reader)?,
+            stamp: 0,
+        })
+    }
 }
@@ -0,0 +1,268 @@
+// Copyright 2021 Peter Williams <pwil3058@gmail.com> <pwil3058@bigpond.net.au>
+
+//! A thread safe (`Send` + `Sync`) redundant discrimination tree.
+//!
+//! This is a `RedundantDiscriminationTree` behind a tree wide lock so it
+//! runs exactly the same algorithms and has all of the same features.
+//! Changes are serialized by the write lock so that queries always see a
+//! tree that has been fully reorganized.  Queries either hold the read lock
+//! (see `read()`) or run against a `snapshot()` which only holds it for as
+//! long as it takes to clone the tree.
+//!
+//! Arguments are checked before the write lock is taken so that bad input
+//! does not poison it.  Each change is made to a clone of the tree (cheap
+//! as the mop arena is copy on write) that only replaces the tree once the
+//! change is complete so, should a panic occur part way through a change
+//! anyway, later callers carry on with the tree as it was before it.
+
+use std::fmt::Debug;
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use ordered_collections::OrderedSet;
+
+use crate::snapshot::Snapshot;
+use crate::strength::{Strength, StrengthConfig};
+use crate::RedundantDiscriminationTree;
+
+#[derive(Debug, Default)]
+pub struct SyncRedundantDiscriminationTree<T: Ord + Debug + Clone, S: Strength> {
+    tree: RwLock<RedundantDiscriminationTree<T, S>>,
+}
+
+impl<T: Ord + Debug + Clone, S: Strength> From<RedundantDiscriminationTree<T, S>>
+    for SyncRedundantDiscriminationTree<T, S>
+{
+    fn from(tree: RedundantDiscriminationTree<T, S>) -> Self {
+        Self {
+            tree: RwLock::new(tree),
+        }
+    }
+}
+
+impl<T: Ord + Debug + Clone, S: Strength> SyncRedundantDiscriminationTree<T, S> {
+    pub fn new() -> Self {
+        RedundantDiscriminationTree::new().into()
+    }
+
+    /// See `RedundantDiscriminationTree::with_config()`.
+    pub fn with_config(config: StrengthConfig) -> Self {
+        RedundantDiscriminationTree::with_config(config).into()
+    }
+
+    pub fn into_inner(self) -> RedundantDiscriminationTree<T, S> {
+        self.tree
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Read access to the tree for queries.  Inclusion (etc.) waits until
+    /// the guard is dropped.
+    pub fn read(&self) -> RwLockReadGuard<'_, RedundantDiscriminationTree<T, S>> {
+        self.tree.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, RedundantDiscriminationTree<T, S>> {
+        self.tree.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // NB: the tree is never left half changed (see module docs) so it is
+    // safe to carry on with it after a panic has poisoned the lock.
+    fn update<R, F>(&self, change: F) -> R
+    where
+        F: FnOnce(&mut RedundantDiscriminationTree<T, S>) -> R,
+    {
+        let mut tree = self.write();
+        let mut changed = tree.clone();
+        let result = change(&mut changed);
+        *tree = changed;
+        result
+    }
+
+    /// An immutable view of the tree as it is now that can be queried
+    /// without holding the lock.
+    pub fn snapshot(&self) -> Snapshot<T, S> {
+        self.read().snapshot()
+    }
+
+    pub fn include_excerpt(&self, excerpt: OrderedSet<T>) {
+        self.include_weighted_excerpt(excerpt, 1.0);
+    }
+
+    /// See `RedundantDiscriminationTree::include_weighted_excerpt()`.
+    pub fn include_weighted_excerpt(&self, excerpt: OrderedSet<T>, weight: f64) {
+        self.read().check_weight(weight);
+        self.update(|tree| tree.include_weighted_excerpt(excerpt, weight));
+    }
+
+    /// See `RedundantDiscriminationTree::include_labelled_excerpt()`.
+    pub fn include_labelled_excerpt(&self, excerpt: OrderedSet<T>, label: &str) {
+        self.update(|tree| tree.include_labelled_excerpt(excerpt, label));
+    }
+
+    pub fn include_experience(&self, experience: &[T]) {
+        self.update(|tree| tree.include_experience(experience));
+    }
+
+    /// See `RedundantDiscriminationTree::include_excerpts()`.
+    pub fn include_excerpts<I: IntoIterator<Item = OrderedSet<T>>>(&self, excerpts: I) {
+        // NB: collected first so that the iterator does not run under the lock
+        let excerpts: Vec<OrderedSet<T>> = excerpts.into_iter().collect();
+        self.update(|tree| tree.include_excerpts(excerpts));
+    }
+
+    pub fn decrement_strengths(&self) {
+        self.update(|tree| tree.decrement_strengths());
+    }
+
+    /// See `RedundantDiscriminationTree::prune()`.
+    pub fn prune(&self, threshold: f64) {
+        self.update(|tree| tree.prune(threshold));
+    }
+
+    /// See `RedundantDiscriminationTree::exclude_excerpt()`.
+    pub fn exclude_excerpt(&self, excerpt: &OrderedSet<T>) -> bool {
+        self.update(|tree| tree.exclude_excerpt(excerpt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strength::*;
+    use std::thread;
+
+    fn assert_send_sync<X: Send + Sync>() {}
+
+    #[test]
+    fn is_send_and_sync() {
+        assert_send_sync::<SyncRedundantDiscriminationTree<String, SimpleStrength>>();
+        assert_send_sync::<Snapshot<String, SimpleStrength>>();
+    }
+
+    #[test]
+    fn it_works() {
+        let rdt = SyncRedundantDiscriminationTree::<&str, SimpleStrength>::new();
+        let excerpt: OrderedSet<&str> = vec!["a", "b", "c", "d"].into();
+        assert!(rdt.read().complete_match(&excerpt).is_none());
+        rdt.include_excerpt(excerpt.clone());
+        assert!(rdt.read().complete_match(&excerpt).is_some());
+        rdt.include_experience(&["a", "b", "c"]);
+        rdt.include_experience(&["a", "b", "d"]);
+        rdt.include_experience(&["a", "d"]);
+        assert_eq!(
+            rdt.read()
+                .complete_match(&vec!["d", "b"].into())
+                .unwrap()
+                .elements(),
+            &OrderedSet::<&str>::from(vec!["a", "b", "d"])
+        );
+        assert_eq!(rdt.read().traces().len(), 4);
+        assert_eq!(rdt.read().epitomes().len(), 6);
+
+        rdt.include_experience(&["e", "b", "d"]);
+        let snapshot = rdt.snapshot();
+        assert!(snapshot.complete_match(&vec!["a", "e"].into()).is_none());
+        assert_eq!(
+            snapshot.partial_matches(&vec!["a", "d", "e"].into()).len(),
+            2
+        );
+        assert_eq!(snapshot.traces().len(), 5);
+        assert_eq!(snapshot.epitomes().len(), 9);
+        assert!(rdt.exclude_excerpt(&vec!["e", "b", "d"].into()));
+        rdt.prune(0.0);
+        rdt.decrement_strengths();
+        assert_eq!(rdt.read().traces().len(), 4);
+        assert_eq!(snapshot.traces().len(), 5);
+        assert_eq!(rdt.into_inner().epitomes().len(), 6);
+    }
+
+    #[test]
+    fn bad_weights_do_not_poison_the_lock() {
+        let rdt = SyncRedundantDiscriminationTree::<u8, CountStrength>::new();
+        rdt.include_excerpt(vec![1, 2].into());
+        let result = thread::scope(|scope| {
+            scope
+                .spawn(|| rdt.include_weighted_excerpt(vec![1, 3].into(), -1.0))
+                .join()
+        });
+        assert!(result.is_err());
+        assert!(!rdt.tree.is_poisoned());
+        rdt.include_weighted_excerpt(vec![1, 3].into(), 2.0);
+        let tree = rdt.read();
+        assert_eq!(tree.traces().len(), 2);
+        assert_eq!(
+            tree.complete_match(&vec![1].into()).unwrap().undif_count(),
+            3
+        );
+    }
+
+    #[test]
+    fn panics_leave_the_tree_unchanged() {
+        let rdt = SyncRedundantDiscriminationTree::<u8, CountStrength>::new();
+        rdt.include_excerpt(vec![1, 2].into());
+        let result = thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    rdt.update(|tree| {
+                        tree.include_excerpt(vec![2, 3].into());
+                        panic!("part way through a change");
+                    })
+                })
+                .join()
+        });
+        assert!(result.is_err());
+        let tree = rdt.read();
+        assert_eq!(tree.traces().len(), 1);
+        assert_eq!(
+            tree.complete_match(&vec![2].into()).unwrap().undif_count(),
+            1
+        );
+        assert!(tree.verify_tree(crate::MopId::ROOT));
+    }
+
+    #[test]
+    fn concurrent_include_and_query() {
+        let rdt = SyncRedundantDiscriminationTree::<String, SimpleStrength>::new();
+        let experiences: Vec<Vec<String>> = vec![
+            vec!["a", "b", "c", "d"],
+            vec!["a", "b", "c"],
+            vec!["a", "b", "d"],
+            vec!["a", "d"],
+            vec!["e", "b", "d"],
+        ]
+        .into_iter()
+        .map(|v| v.into_iter().map(String::from).collect())
+        .collect();
+        thread::scope(|scope| {
+            for experience in experiences.iter() {
+                let rdt = &rdt;
+                scope.spawn(move || rdt.include_experience(experience));
+            }
+            for i in 0..4 {
+                let rdt = &rdt;
+                scope.spawn(move || {
+                    let query: OrderedSet<String> = vec!["a".to_string(), "d".to_string()].into();
+                    for _ in 0..10 {
+                        if i % 2 == 0 {
+                            for mop in rdt.read().partial_matches(&query).iter() {
+                                assert!(!mop.elements().is_disjoint(&query));
+                            }
+                        } else {
+                            let snapshot = rdt.snapshot();
+                            for mop in snapshot.partial_matches(&query).iter() {
+                                assert!(!mop.elements().is_disjoint(&query));
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        let tree = rdt.read();
+        assert_eq!(tree.traces().len(), 5);
+        assert_eq!(tree.epitomes().len(), 9);
+        for experience in experiences.iter() {
+            let excerpt: OrderedSet<String> = experience.iter().collect();
+            assert_eq!(tree.complete_match(&excerpt).unwrap().elements(), &excerpt);
+        }
+    }
+}